@@ -1,54 +1,641 @@
-use clap::Parser;
+use crate::condition::Condition;
+use crate::error::OckError;
+use clap::parser::ValueSource;
+use clap::{ArgAction, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use std::fs;
-use std::io::{self, BufRead};
+use std::io::{self, BufRead, Read};
 use std::path::Path;
 
+/// Formats selected rows can be printed in
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Aligned columns
+    Text,
+    /// Cells separated by literal tabs, with no alignment padding
+    Tsv,
+    /// `key = "value"` pairs keyed by header names, or an array of tables for several rows
+    Toml,
+    /// HTML table, with the header's names as header cells
+    Html,
+    /// Array of objects keyed by header names
+    Json,
+    /// List of maps keyed by header names, or of lists without a header
+    Yaml,
+    /// Each row as a block of `header: value` lines, with a blank line between rows
+    Records,
+}
+
+impl OutputFormat {
+    /// Whether the format keys cells by header name, rather than printing the header as a row
+    pub fn is_structured(self) -> bool {
+        match self {
+            OutputFormat::Text | OutputFormat::Tsv => false,
+            OutputFormat::Toml
+            | OutputFormat::Html
+            | OutputFormat::Json
+            | OutputFormat::Yaml
+            | OutputFormat::Records => true,
+        }
+    }
+}
+
+/// Formats input can be read in
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum InputFormat {
+    /// Rows and columns split by delimiters
+    Text,
+    /// Array of objects (keyed by header names) or of arrays
+    Json,
+}
+
 /// CLI arguments parsed here
 /// All parsing handled by the `clap` crate
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Rows to select from input
-    #[arg(short, long, allow_negative_numbers = true, default_value="")]
-    pub rows: String,
+    /// Rows to select from input, can be given more than once
+    #[arg(short, long, allow_hyphen_values = true, action = ArgAction::Append)]
+    pub rows: Vec<String>,
+
+    /// Select the first row, same as `-r 1`
+    #[arg(long, conflicts_with_all = ["rows", "rows_file"])]
+    pub first: bool,
+
+    /// Select the last row, same as `-r -1`
+    #[arg(long, conflicts_with_all = ["rows", "rows_file"])]
+    pub last: bool,
+
+    /// File to read row selectors from, added after any given with --rows
+    #[arg(long)]
+    pub rows_file: Option<String>,
+
+    /// Select rows from the first one matching this regex, through the row matching --to (or the
+    /// last row). Added to any other row selectors, and colons and commas need no escaping
+    #[arg(long, value_name = "PATTERN", allow_hyphen_values = true)]
+    pub from: Option<String>,
+
+    /// Select rows through the first one matching this regex after --from (or from the first
+    /// row). Added to any other row selectors, and colons and commas need no escaping
+    #[arg(long, value_name = "PATTERN", allow_hyphen_values = true)]
+    pub to: Option<String>,
+
+    /// Also output N rows after each selected row
+    #[arg(short = 'A', long, value_name = "N")]
+    pub after: Option<usize>,
 
-    /// Row delimiter
+    /// Also output N rows before each selected row
+    #[arg(short = 'B', long, value_name = "N")]
+    pub before: Option<usize>,
+
+    /// Also output N rows before and after each selected row
+    #[arg(short = 'C', long, value_name = "N", default_value_t = 0)]
+    pub context: usize,
+
+    /// Ignore case when matching regex selectors (the default)
+    #[arg(short, long, overrides_with = "case_sensitive")]
+    pub ignore_case: bool,
+
+    /// Match regex selectors case sensitively
+    #[arg(long, overrides_with = "ignore_case")]
+    pub case_sensitive: bool,
+
+    /// Extra flags for regex selectors, e.g. `ms` so `^`/`$` match at line breaks and `.` matches
+    /// newlines, for rows that contain them
+    #[arg(long, value_name = "FLAGS")]
+    pub regex_flags: Option<String>,
+
+    /// Treat regex selectors as globs matching the whole header or row, e.g. `cpu*`, where `*`
+    /// matches anything, `?` any one character, and `[...]` one of a set of characters
+    #[arg(long)]
+    pub glob: bool,
+
+    /// Count rows and columns from 0 rather than 1. Negative indices still count back from -1
+    #[arg(long)]
+    pub zero_based: bool,
+
+    /// End regex ranges just before the row or column matching the end regex, rather than on it
+    #[arg(long)]
+    pub exclusive_end: bool,
+
+    /// Select every block a regex range matches, starting the range again each time its start
+    /// regex matches after it has ended
+    #[arg(long)]
+    pub repeat_ranges: bool,
+
+    /// Replace tabs in the input with spaces up to every Nth column before splitting, like
+    /// `expand -t N`
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub expand_tabs: Option<usize>,
+
+    /// Format to read input in. JSON input is an array of records, each of which becomes a row,
+    /// with the keys of an array of objects as the header
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = InputFormat::Text,
+        conflicts_with_all = [
+            "row_delimiter",
+            "column_delimiter",
+            "csv_input",
+            "fixed_width",
+            "auto_delimiter",
+            "paragraph",
+            "group_by_blank"
+        ]
+    )]
+    pub input_format: InputFormat,
+
+    /// Row delimiter, or `OCK_ROW_DELIMITER` if that's set and this isn't given
     #[arg(long, default_value = r"\n")]
     pub row_delimiter: String,
 
-    /// Columns to select from input
-    #[arg(short, long, allow_negative_numbers = true, default_value="")]
-    pub columns: String,
+    /// Treat paragraphs separated by blank lines as rows, like awk's `RS=""`, and output each one
+    /// whole
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "row_delimiter",
+            "columns",
+            "columns_file",
+            "cell_regex",
+            "csv_input",
+            "group_by_blank"
+        ]
+    )]
+    pub paragraph: bool,
+
+    /// Treat the row delimiter as a literal string rather than a regex (escapes like `\t` still
+    /// work)
+    #[arg(long)]
+    pub row_delimiter_literal: bool,
+
+    /// Keep the carriage return at the end of CRLF-terminated rows
+    #[arg(long)]
+    pub keep_cr: bool,
 
-    /// Column delimiter
-    #[arg(long, default_value = r"\s")]
+    /// Number of input rows to ignore before selecting anything
+    #[arg(long, default_value_t = 0)]
+    pub skip: usize,
+
+    /// Row to match column selectors against (1-based), rows before it are skipped unless selected
+    #[arg(
+        long,
+        default_value_t = 1,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub header_row: usize,
+
+    /// Input has no header, so the first row is data like any other. Only numeric column selectors
+    /// can be used, as there are no names to match
+    #[arg(long, conflicts_with_all = ["header_row", "per_row_headers", "group_by_blank"])]
+    pub no_header: bool,
+
+    /// Columns to select from input, can be given more than once
+    #[arg(short, long, allow_hyphen_values = true, action = ArgAction::Append)]
+    pub columns: Vec<String>,
+
+    /// File to read column selectors from, added after any given with --columns
+    #[arg(long)]
+    pub columns_file: Option<String>,
+
+    /// Column delimiter, or `OCK_COLUMN_DELIMITER` if that's set and this isn't given. With a
+    /// single column index and no row selectors, behaves like `cut -d`
+    #[arg(short = 'd', long, default_value = r"\s")]
     pub column_delimiter: String,
 
+    /// Select the columns with a cell matching this regex in any row, rather than matching
+    /// selectors against the header. Every row is read before anything is output
+    #[arg(
+        long,
+        value_name = "PATTERN",
+        conflicts_with_all = ["columns", "columns_file", "per_row_headers", "cut_compat"]
+    )]
+    pub cell_regex: Option<String>,
+
+    /// Split rows into columns at fixed character offsets rather than on a delimiter, given as
+    /// 0-based inclusive ranges, e.g. `0-7,8-15,16-`. Padding is trimmed from each cell
+    #[arg(
+        long,
+        value_name = "RANGES",
+        conflicts_with_all = ["fixed_strings", "delimiter_preserve", "cut_compat"]
+    )]
+    pub fixed_width: Option<String>,
+
+    /// Read input as CSV, so quoted fields can contain commas, newlines, and doubled quotes.
+    /// Fields are comma-separated, unless a single character column delimiter is given
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "fixed_width",
+            "fixed_strings",
+            "delimiter_preserve",
+            "cut_compat",
+            "row_delimiter_literal"
+        ]
+    )]
+    pub csv_input: bool,
+
+    /// Treat the column delimiter as a literal string rather than a regex (escapes like `\t` still
+    /// work)
+    #[arg(short, long)]
+    pub fixed_strings: bool,
+
+    /// Work out whether columns are separated by commas, tabs, or whitespace from the first few
+    /// rows, rather than using --column-delimiter
+    #[arg(long, conflicts_with_all = ["column_delimiter", "fixed_width"])]
+    pub auto_delimiter: bool,
+
+    /// Report what was worked out from the input (e.g. the delimiter) on stderr
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Select fields exactly like `cut -d X -f LIST`: the delimiter (a tab by default) is literal,
+    /// empty fields count, there's no header, and fields past the end of a row are left out.
+    /// Only numeric column selectors are allowed
+    #[arg(long, conflicts_with_all = ["rows", "rows_file", "first", "last", "strict"])]
+    pub cut_compat: bool,
+
+    /// Keep the columns that aren't selected instead. Without column selectors, this keeps none
+    #[arg(long)]
+    pub invert_columns: bool,
+
+    /// Only output rows meeting a condition on one of their columns, written as `COLUMN OP VALUE`
+    /// where OP is `=`, `!=`, `~` (contains), `>`, or `<` (numeric), e.g. `command~python`.
+    /// The column is matched against the header like -c. Can be given more than once, in which
+    /// case rows have to meet every condition
+    #[arg(
+        long = "where",
+        value_name = "CONDITION",
+        value_parser = crate::condition::parse_condition,
+        action = ArgAction::Append
+    )]
+    pub conditions: Vec<Condition>,
+
+    /// Match column selectors against each row's own cells instead of the header row's.
+    /// Slower, as columns are worked out again for every row.
+    #[arg(long)]
+    pub per_row_headers: bool,
+
+    /// Count negative column indices back from the end of each row rather than the header, for
+    /// rows with different numbers of columns
+    #[arg(long, conflicts_with_all = ["per_row_headers", "cell_regex", "cut_compat"])]
+    pub per_row_indices: bool,
+
+    /// Treat each block of rows between blank rows as its own table, matching column selectors
+    /// against each block's first row rather than the header. Each table's header and the blank
+    /// row between tables are output as rows, so options that reorder, drop, or total rows can't
+    /// be used
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "per_row_headers",
+            "per_row_indices",
+            "cell_regex",
+            "csv_input",
+            "sort",
+            "sort_numeric",
+            "unique",
+            "tail",
+            "sum",
+            "mean",
+            "count_unique",
+        ]
+    )]
+    pub group_by_blank: bool,
+
+    /// Output runs of consecutive columns as they appear in the input, keeping the original
+    /// spacing between them
+    #[arg(long)]
+    pub preserve_spacing: bool,
+
+    /// Join the selected cells of each row into one, separated by SEP (escapes like `\t` work)
+    #[arg(long, value_name = "SEP")]
+    pub join: Option<String>,
+
+    /// Rearrange the selected columns, given as comma-separated (1-based) positions among them,
+    /// e.g. `3,1,2`
+    #[arg(
+        long,
+        value_name = "POSITIONS",
+        value_delimiter = ',',
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub column_order: Vec<usize>,
+
+    /// Output rows in the order their selectors were given rather than input order, so
+    /// `-r 5,1,3` prints row 5 first. Ranges keep their own order.
+    #[arg(long)]
+    pub preserve_order: bool,
+
+    /// Keep the column delimiter on the end of the cell before it, rather than dropping it
+    #[arg(long, conflicts_with = "preserve_spacing")]
+    pub delimiter_preserve: bool,
+
+    /// Trim leading and trailing whitespace from each selected cell
+    #[arg(long)]
+    pub trim: bool,
+
+    /// Squeeze runs of whitespace inside each selected cell down to a single space, e.g. for cells
+    /// kept together with --preserve-spacing
+    #[arg(long)]
+    pub collapse_whitespace: bool,
+
+    /// Placeholder for selected columns missing from short rows, which are skipped otherwise
+    #[arg(long)]
+    pub fill: Option<String>,
+
+    /// Collapse the gaps left by empty cells in the output
+    #[arg(long)]
+    pub squeeze: bool,
+
+    /// Pad every column of the output to at least this many characters
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub min_col_width: usize,
+
+    /// Character to pad columns of the output with, e.g. `.` for dot leaders
+    #[arg(long, value_name = "C")]
+    pub pad_char: Option<char>,
+
+    /// Count every character as one column wide when aligning, rather than two for wide characters
+    /// (e.g. CJK and emoji) and none for combining marks
+    #[arg(long)]
+    pub ascii_width: bool,
+
+    /// Put commas between groups of three digits in cells that are integers, e.g. `129,876`, when
+    /// aligning
+    #[arg(long)]
+    pub group_digits: bool,
+
+    /// Show tabs, carriage returns, and other non-printable characters in cells as escapes like
+    /// `\t`, `\r`, and `\xNN`, like `cat -v`
+    #[arg(long)]
+    pub escape_output: bool,
+
+    /// Line selected cells up by their column in the input rather than their position among the
+    /// selected cells, so cells from ragged rows stay under their own column in text output
+    #[arg(long, conflicts_with_all = ["preserve_spacing", "join", "column_order", "squeeze"])]
+    pub align_by_index: bool,
+
+    /// Leave out output rows where every selected cell is empty
+    #[arg(long)]
+    pub squeeze_blank_rows: bool,
+
+    /// Leave out output rows identical to one already printed, keeping the order they first
+    /// appeared in
+    #[arg(short, long)]
+    pub unique: bool,
+
+    /// Sort output rows by this column of the output (1-based), keeping the header at the top
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub sort: Option<usize>,
+
+    /// Sort output rows numerically by this column of the output (1-based). Cells that aren't
+    /// numbers are sorted after those that are
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        conflicts_with = "sort",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub sort_numeric: Option<usize>,
+
+    /// Only output the last N rows, after the header if it's printed
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub tail: Option<usize>,
+
+    /// Print the sum of this column of the output (1-based) instead of the rows. Cells that
+    /// aren't numbers are skipped
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub sum: Option<usize>,
+
+    /// Print the mean of this column of the output (1-based) instead of the rows, after the sum if
+    /// that's asked for too. Cells that aren't numbers are skipped
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub mean: Option<usize>,
+
+    /// Print each distinct value in this column of the output (1-based) with how many times it
+    /// appears, most common first, instead of the rows. Like `sort | uniq -c | sort -rn`
+    #[arg(
+        long,
+        value_name = "COLUMN",
+        conflicts_with_all = ["sum", "mean"],
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub count_unique: Option<usize>,
+
+    /// Swap rows and columns of the output, so each selected row is printed as a column
+    #[arg(long)]
+    pub transpose: bool,
+
+    /// Reprint the selected header cells every N rows (0 to disable)
+    #[arg(long, default_value_t = 0)]
+    pub header_repeat: usize,
+
+    /// Format to print selected rows in
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output_format: OutputFormat,
+
+    /// For JSON output, print an object with the header's names as `columns` and each row as an
+    /// array under `rows`, rather than an array of objects
+    #[arg(long)]
+    pub with_header_meta: bool,
+
+    /// Separate output cells with tabs rather than aligning them, shorthand for
+    /// `--output-format tsv`. Tabs, newlines, and backslashes in cells are escaped as `\t`, `\n`,
+    /// and `\\`
+    #[arg(long, conflicts_with = "output_format")]
+    pub tsv: bool,
+
+    /// Print each row as a block of `header: value` lines, like mysql's `\G`, shorthand for
+    /// `--output-format records`. Handy for rows with many columns
+    #[arg(short = 'G', long, conflicts_with_all = ["output_format", "tsv"])]
+    pub vertical: bool,
+
+    /// When printing to a terminal, stop once it's full and say how many rows were left out
+    #[arg(long)]
+    pub page: bool,
+
+    /// Output row delimiter
+    #[arg(long, default_value = r"\n")]
+    pub output_row_delimiter: String,
+
+    /// Don't print a row delimiter after the last row
+    #[arg(long)]
+    pub no_trailing_newline: bool,
+
+    /// Don't print anything, only exit with 0 if something was selected and 1 if not
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Exit with an error when a selector's index is out of bounds, or it matches nothing,
+    /// rather than leaving it out of the output
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Print how selectors were parsed and which columns they match, then exit
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Print how long reading input, splitting it into rows, and selecting rows took to stderr
+    #[arg(long, hide = true)]
+    pub timing: bool,
+
+    /// Print the number of columns each row splits into, from the header row on, then exit
+    #[arg(long, conflicts_with = "list_columns")]
+    pub count_columns: bool,
+
+    /// Print how many columns each column selector matched to stderr
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Print the header row's cells picked by the column selectors, then exit
+    #[arg(
+        long,
+        conflicts_with_all = ["no_header", "cut_compat", "per_row_headers", "group_by_blank"]
+    )]
+    pub header_only: bool,
+
+    /// Print the 0-based indices of the selected columns as a comma-separated list, then exit
+    #[arg(long, conflicts_with_all = ["cut_compat", "per_row_headers", "group_by_blank"])]
+    pub print_indices: bool,
+
+    /// Print the header row's columns with their indices, then exit
+    #[arg(long)]
+    pub list_columns: bool,
+
+    /// Read stdin even when a file or text is given, putting it before them, e.g. to add a header
+    #[arg(long)]
+    pub stdin: bool,
+
     /// Text to parse
     #[arg(value_delimiter = None, default_value = "", help="Text to parse")]
     pub input: String,
+
+    /// Whether the column delimiter came from `OCK_COLUMN_DELIMITER` rather than the command line
+    #[arg(skip)]
+    pub column_delimiter_from_env: bool,
 }
 
-/// Read String from stdin (allow piped input)
-/// Shoutout to Frazer's Stack Overflow answer (https://stackoverflow.com/a/73157621)
-fn read_stdin() -> String {
-    io::stdin()
-        .lock()
-        .lines()
-        .fold("".to_string(), |acc, line| acc + &line.unwrap() + "\n")
-        .to_string()
+impl Args {
+    /// Parse arguments from the command line, taking delimiters that weren't given from the
+    /// `OCK_COLUMN_DELIMITER` and `OCK_ROW_DELIMITER` environment variables, if they're set, before
+    /// falling back on the built-in defaults. Modes that split input their own way ignore them.
+    pub fn parse_with_env() -> Args {
+        let matches = Args::command().get_matches();
+        let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        let env_default = |id: &str, var: &str| {
+            let is_default = matches.value_source(id) == Some(ValueSource::DefaultValue);
+            std::env::var(var).ok().filter(|value| is_default && !value.is_empty())
+        };
+        let json_input = args.input_format == InputFormat::Json;
+        if !json_input && !args.auto_delimiter && args.fixed_width.is_none() {
+            if let Some(delimiter) = env_default("column_delimiter", "OCK_COLUMN_DELIMITER") {
+                args.column_delimiter = delimiter;
+                args.column_delimiter_from_env = true;
+            }
+        }
+        if !json_input && !args.paragraph {
+            if let Some(delimiter) = env_default("row_delimiter", "OCK_ROW_DELIMITER") {
+                args.row_delimiter = delimiter;
+            }
+        }
+        args
+    }
+}
+
+/// Read String from stdin (allow piped input) exactly as given, leaving splitting into rows to the
+/// row delimiter. Nothing is added, so input without a final newline stays that way.
+fn read_stdin() -> Result<String, OckError> {
+    let mut input = String::new();
+    io::stdin().lock().read_to_string(&mut input).map_err(stdin_error)?;
+    Ok(input)
+}
+
+/// Error for stdin that couldn't be read
+fn stdin_error(source: io::Error) -> OckError {
+    OckError::Io { path: String::from("stdin"), source }
+}
+
+/// Read the first few non-empty lines from stdin, for when only the rows up to the header are
+/// needed. Empty lines are skipped, as they are when splitting rows.
+pub fn read_stdin_lines(count: usize) -> Result<String, OckError> {
+    let mut input = String::new();
+    let mut lines = io::stdin().lock().lines();
+    let mut line_count = 0;
+    // Stop as soon as there are enough lines, rather than waiting on the rest of stdin
+    while line_count < count {
+        let Some(line) = lines.next() else { break };
+        let line = line.map_err(stdin_error)?;
+        if !line.is_empty() {
+            input.push_str(&line);
+            input.push('\n');
+            line_count += 1;
+        }
+    }
+    Ok(input)
+}
+
+/// File extensions that suggest input text was meant to be a file
+const FILE_EXTENSIONS: [&str; 7] = ["csv", "tsv", "txt", "log", "json", "dat", "out"];
+
+/// Whether input text looks like a path to a file, rather than text to parse
+fn looks_like_path(text: &str) -> bool {
+    let has_file_extension = Path::new(text)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| FILE_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+    !text.contains(char::is_whitespace) && (text.contains('/') || has_file_extension)
 }
 
 /// Parse input, allowing file, piped text, or text as an argument
-pub fn parse_input(input_text: &String) -> String {
-    if input_text == "" {
+/// Files that exist but can't be read are an error, rather than being treated as text
+pub fn parse_input(input_text: &str) -> Result<String, OckError> {
+    if input_text.is_empty() {
         // If not input passed, read stdin (i.e. input from pipe)
         read_stdin()
     } else if Path::new(input_text).exists() {
         // If input string is an extant file, read its content as input
-        fs::read_to_string(input_text).expect("Input file could not be read.")
+        fs::read_to_string(input_text)
+            .map_err(|source| OckError::Io { path: input_text.to_string(), source })
     } else {
         // If input string is present and not file, use it as input args.input
-        input_text.clone()
+        if looks_like_path(input_text) {
+            eprintln!("warning: no file '{}', so treating it as text to parse", input_text);
+        }
+        Ok(input_text.to_string())
+    }
+}
+
+/// Read stdin followed by any input given as an argument (a file or text), for --stdin. A newline
+/// is put between them if stdin doesn't end with one, so its last row isn't joined to the first of
+/// the rest.
+pub fn parse_input_after_stdin(input_text: &str) -> Result<String, OckError> {
+    let mut input = read_stdin()?;
+    if !input_text.is_empty() {
+        if !input.is_empty() && !input.ends_with('\n') {
+            input.push('\n');
+        }
+        input.push_str(&parse_input(input_text)?);
     }
+    Ok(input)
 }
@@ -0,0 +1,81 @@
+use std::fmt;
+
+/// Ways a cell can be compared against a value in a condition
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    /// Cell is exactly the value
+    Equal,
+    /// Cell is anything but the value
+    NotEqual,
+    /// Cell contains the value
+    Contains,
+    /// Cell is a number greater than the value
+    GreaterThan,
+    /// Cell is a number less than the value
+    LessThan,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Equal => "=",
+            Operator::NotEqual => "!=",
+            Operator::Contains => "~",
+            Operator::GreaterThan => ">",
+            Operator::LessThan => "<",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Condition a row has to meet to be output, e.g. `command~python`
+#[derive(Clone, Debug)]
+pub struct Condition {
+    /// Column selector picking the column to check, matched against the header like `-c`
+    pub column: String,
+
+    /// How to compare the column's cell against the value
+    pub operator: Operator,
+
+    /// Value to compare the cell against
+    pub value: String,
+}
+
+impl Condition {
+    /// Check whether a cell meets the condition. Numeric comparisons are never met by cells (or
+    /// values) that aren't numbers.
+    pub fn matches(&self, cell: &str) -> bool {
+        let numbers = || Some((cell.trim().parse::<f64>().ok()?, self.value.parse::<f64>().ok()?));
+        match self.operator {
+            Operator::Equal => cell == self.value,
+            Operator::NotEqual => cell != self.value,
+            Operator::Contains => cell.contains(&self.value),
+            Operator::GreaterThan => numbers().is_some_and(|(cell, value)| cell > value),
+            Operator::LessThan => numbers().is_some_and(|(cell, value)| cell < value),
+        }
+    }
+}
+
+/// Parse a condition written as `COLUMN OP VALUE`, where OP is `=`, `!=`, `~` (contains), `>`, or
+/// `<`. The first operator found splits the column from the value, so the value can contain them.
+pub fn parse_condition(text: &str) -> Result<Condition, String> {
+    let operator_at = text.char_indices().find_map(|(idx, c)| {
+        let operator = match c {
+            '!' if text[idx..].starts_with("!=") => Operator::NotEqual,
+            '=' => Operator::Equal,
+            '~' => Operator::Contains,
+            '>' => Operator::GreaterThan,
+            '<' => Operator::LessThan,
+            _ => return None,
+        };
+        Some((idx, operator))
+    });
+    let (idx, operator) = operator_at
+        .ok_or_else(|| format!("no operator in '{}', expected one of = != ~ > <", text))?;
+    let column = &text[..idx];
+    if column.is_empty() {
+        return Err(format!("no column before '{}' in '{}'", operator, text));
+    }
+    let value = &text[idx + operator.to_string().len()..];
+    Ok(Condition { column: column.to_string(), operator, value: value.to_string() })
+}
@@ -0,0 +1,59 @@
+use crate::selector::SelectorError;
+use std::fmt;
+use std::io;
+
+/// Errors that stop ock from selecting anything, covering selectors, reading input, and formats
+#[derive(Debug)]
+pub enum OckError {
+    /// Row or column selector couldn't be parsed
+    Selector(SelectorError),
+
+    /// Input or a selector file couldn't be read, with `path` being `stdin` for piped input
+    Io { path: String, source: io::Error },
+
+    /// An option or the input doesn't fit the format asked for, e.g. a delimiter that isn't a
+    /// valid regex, or invalid JSON input
+    Format(String),
+
+    /// A selector was out of bounds or selected nothing under `--strict`
+    Strict(String),
+}
+
+impl fmt::Display for OckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OckError::Selector(error) => write!(f, "{}", error),
+            OckError::Io { path, source } => write!(f, "could not read '{}': {}", path, source),
+            OckError::Format(message) | OckError::Strict(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for OckError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OckError::Selector(error) => Some(error),
+            OckError::Io { source, .. } => Some(source),
+            OckError::Format(_) | OckError::Strict(_) => None,
+        }
+    }
+}
+
+impl From<SelectorError> for OckError {
+    fn from(error: SelectorError) -> OckError {
+        OckError::Selector(error)
+    }
+}
+
+impl OckError {
+    /// Code to exit with. Like grep, 1 means nothing was selected, so errors start at 2, which is
+    /// also what clap exits with for arguments it can't parse
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            OckError::Selector(_) => 2,
+            OckError::Format(_) => 3,
+            OckError::Io { .. } => 4,
+            OckError::Strict(_) => 5,
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::output::json_string;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A parsed JSON value. Numbers are kept as they were written, as they only end up as text.
+#[derive(Debug)]
+enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// Text for the value as a cell. Strings are unquoted, null is empty, and arrays and objects
+    /// are written back out as compact JSON.
+    fn to_cell(&self) -> String {
+        match self {
+            JsonValue::String(text) => text.clone(),
+            JsonValue::Null => String::new(),
+            value => value.to_json(),
+        }
+    }
+
+    /// The value written as compact JSON
+    fn to_json(&self) -> String {
+        match self {
+            JsonValue::Null => String::from("null"),
+            JsonValue::Bool(value) => value.to_string(),
+            JsonValue::Number(number) => number.clone(),
+            JsonValue::String(text) => json_string(text),
+            JsonValue::Array(items) => {
+                let items: Vec<String> = items.iter().map(JsonValue::to_json).collect();
+                format!("[{}]", items.join(","))
+            }
+            JsonValue::Object(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{}:{}", json_string(key), value.to_json()))
+                    .collect();
+                format!("{{{}}}", pairs.join(","))
+            }
+        }
+    }
+}
+
+/// Just enough of a JSON parser to read records, walking the text one character at a time
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected '{}' but found '{}'", expected, c)),
+            None => Err(format!("expected '{}' but the input ended", expected)),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_object(),
+            Some('"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                let is_number_char = |c: &char| "+-.eE".contains(*c) || c.is_ascii_digit();
+                while let Some(c) = self.chars.next_if(is_number_char) {
+                    number.push(c);
+                }
+                Ok(JsonValue::Number(number))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphabetic()) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "true" => Ok(JsonValue::Bool(true)),
+                    "false" => Ok(JsonValue::Bool(false)),
+                    "null" => Ok(JsonValue::Null),
+                    _ => Err(format!("unexpected '{}'", word)),
+                }
+            }
+            Some(c) => Err(format!("unexpected '{}'", c)),
+            None => Err(String::from("the input ended before a value")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        self.expect('[')?;
+        let mut items: Vec<JsonValue> = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(items)),
+                _ => return Err(String::from("expected ',' or ']' in an array")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        self.expect('{')?;
+        let mut pairs: Vec<(String, JsonValue)> = Vec::new();
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(JsonValue::Object(pairs));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.expect(':')?;
+            pairs.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(pairs)),
+                _ => return Err(String::from("expected ',' or '}' in an object")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(text),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('r') => text.push('\r'),
+                    Some('b') => text.push('\u{8}'),
+                    Some('f') => text.push('\u{c}'),
+                    Some('u') => text.push(self.parse_unicode_escape()?),
+                    Some(c) => text.push(c),
+                    None => return Err(String::from("the input ended inside a string")),
+                },
+                Some(c) => text.push(c),
+                None => return Err(String::from("the input ended inside a string")),
+            }
+        }
+    }
+
+    /// Read the four hex digits after a `\u`
+    fn parse_hex_code(&mut self) -> Result<u32, String> {
+        let hex: String = (0..4).filter_map(|_| self.chars.next()).collect();
+        u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape '\\u{}'", hex))
+    }
+
+    /// Read the hex digits of a `\uXXXX` escape, along with the second half of a surrogate pair
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let code = self.parse_hex_code()?;
+        let code = if (0xD800..0xDC00).contains(&code) {
+            // High surrogate, which needs the low surrogate escaped after it
+            let is_escape = self.chars.next() == Some('\\') && self.chars.next() == Some('u');
+            let low = if is_escape { self.parse_hex_code()? } else { 0 };
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(String::from("unpaired surrogate in a '\\u' escape"));
+            }
+            0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            code
+        };
+        char::from_u32(code).ok_or_else(|| format!("invalid character in escape '{:X}'", code))
+    }
+}
+
+/// Quote a cell as a CSV field, so delimiters, quotes, and newlines in it stay part of it
+fn csv_field(cell: &str) -> String {
+    format!("\"{}\"", cell.replace('"', "\"\""))
+}
+
+/// Flatten a JSON array of records into CSV, one record per row, so selectors work on it like any
+/// other table. Arrays of objects get a header of every key, in the order they first appear, with
+/// empty cells for keys a record doesn't have. Arrays of arrays are used as they are.
+pub fn json_to_csv(text: &str) -> Result<String, String> {
+    let mut parser = JsonParser { chars: text.chars().peekable() };
+    let records = match parser.parse_value()? {
+        JsonValue::Array(records) => records,
+        _ => return Err(String::from("JSON input has to be an array of records")),
+    };
+    parser.skip_whitespace();
+    if parser.chars.peek().is_some() {
+        return Err(String::from("unexpected text after the JSON array"));
+    }
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    if records.iter().all(|record| matches!(record, JsonValue::Object(_))) {
+        let mut header: Vec<String> = Vec::new();
+        for record in &records {
+            if let JsonValue::Object(pairs) = record {
+                for (key, _) in pairs {
+                    if !header.contains(key) {
+                        header.push(key.clone());
+                    }
+                }
+            }
+        }
+        for record in &records {
+            if let JsonValue::Object(pairs) = record {
+                let cell = |key: &String| {
+                    let value = pairs.iter().find(|(other, _)| other == key);
+                    value.map_or(String::new(), |(_, value)| value.to_cell())
+                };
+                rows.push(header.iter().map(cell).collect());
+            }
+        }
+        if !header.is_empty() {
+            rows.insert(0, header);
+        }
+    } else {
+        for record in &records {
+            match record {
+                JsonValue::Array(cells) => {
+                    rows.push(cells.iter().map(JsonValue::to_cell).collect())
+                }
+                JsonValue::Object(_) => {
+                    return Err(String::from("JSON records have to be all objects or all arrays"))
+                }
+                // A lone value is a record with one cell
+                value => rows.push(vec![value.to_cell()]),
+            }
+        }
+    }
+    let lines: Vec<String> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| csv_field(cell)).collect::<Vec<String>>().join(","))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_get_a_header_of_every_key() {
+        let text = r#"[{"pid": 1, "cmd": "init"}, {"cmd": "bash", "tty": null}]"#;
+        let expected = "\"pid\",\"cmd\",\"tty\"\n\"1\",\"init\",\"\"\n\"\",\"bash\",\"\"";
+        assert_eq!(json_to_csv(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn nested_values_are_written_back_out_as_json() {
+        let text = r#"[{"tags": ["a", "b"], "meta": {"ok": true}, "note": "say \"hi\""}]"#;
+        let expected = "\"tags\",\"meta\",\"note\"\n\"[\"\"a\"\",\"\"b\"\"]\",\
+                        \"{\"\"ok\"\":true}\",\"say \"\"hi\"\"\"";
+        assert_eq!(json_to_csv(text).unwrap(), expected);
+    }
+
+    #[test]
+    fn unicode_escapes_decode_surrogate_pairs() {
+        let text = r#"[{"emoji": "\ud83d\ude00", "accent": "caf\u00e9"}]"#;
+        assert_eq!(json_to_csv(text).unwrap(), "\"emoji\",\"accent\"\n\"\u{1F600}\",\"café\"");
+        assert!(json_to_csv(r#"[{"a": "\ud83d"}]"#).is_err());
+        assert!(json_to_csv(r#"[{"a": "\ud83dA"}]"#).is_err());
+    }
+
+    #[test]
+    fn invalid_records_are_an_error() {
+        assert!(json_to_csv(r#"{"a": 1}"#).is_err());
+        assert!(json_to_csv(r#"[{"a": 1}, [1]]"#).is_err());
+        assert!(json_to_csv(r#"[{"a": 1}] extra"#).is_err());
+        assert!(json_to_csv(r#"[{"a": 1}"#).is_err());
+    }
+}
@@ -1,132 +1,1535 @@
-use clap::Parser;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io::{self, IsTerminal};
+use std::time::Instant;
 
 mod cli;
+mod condition;
+mod error;
+mod input;
+mod output;
 mod selector;
+mod splitter;
 
 include!("utils.rs");
 
-fn item_in_sequence(item_idx: usize, item: &String, selector: &mut selector::Selector) -> bool {
-    let mut in_sequence = false;
-    if item_idx != selector.start_idx
-        && selector.start_idx == selector.end_idx
-        && utils::regex_eq(&selector.start_regex, &selector.end_regex)
-        && !utils::regex_is_default(&selector.start_regex)
-    {
-        // If a regex is provided as the only selector, just check against it
-        return selector.start_regex.is_match(item)
+/// Get the row indices selected when every row selector is a plain numeric range with a step of 1,
+/// without having to check each row in turn. Returns None if any selector needs the full
+/// regex-capable matching in `Selector::matches`.
+fn numeric_row_indices(
+    row_selectors: &[selector::Selector],
+    row_count: usize,
+    preserve_order: bool,
+) -> Option<Vec<usize>> {
+    let mut row_idxs: Vec<usize> = Vec::new();
+    for row_selector in row_selectors {
+        if row_selector.start_regex.is_some()
+            || row_selector.end_regex.is_some()
+            || row_selector.modulo.is_some()
+            || row_selector.filter.is_some()
+            || row_selector.step != 1
+            || row_selector.start_idx > row_selector.end_idx
+        {
+            return None;
+        }
+        // Clamp the range to the rows that exist
+        let start_idx = row_selector.start_idx.max(0);
+        let end_idx = row_selector.end_idx.min(row_count as i64 - 1);
+        if start_idx <= end_idx {
+            row_idxs.extend(start_idx as usize..=end_idx as usize);
+        }
     }
-    if (item_idx == selector.start_idx && utils::regex_is_default(&selector.start_regex))
-        || selector.start_regex.is_match(item)
+    // Rows are output once each in input order, unless they should stay in the order of their
+    // selectors, where a row matched by more than one selector goes with the first of them
+    if preserve_order {
+        let mut seen: HashSet<usize> = HashSet::new();
+        row_idxs.retain(|&row_idx| seen.insert(row_idx));
+    } else {
+        row_idxs.sort();
+        row_idxs.dedup();
+    }
+    Some(row_idxs)
+}
+
+/// Get the ranges of fields (0-based and inclusive) to extract when ock should behave exactly like
+/// `cut -d X -f LIST`. That's when asked to with --cut-compat, or when a column delimiter is given
+/// along with a single column index and no row selectors.
+fn cut_field_ranges(
+    args: &cli::Args,
+    select_options: &SelectOptions,
+    column_selectors: &[selector::Selector],
+) -> Option<Vec<(usize, usize)>> {
+    if args.cut_compat {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for column_selector in column_selectors {
+            // cut only knows about positive field numbers, so anything else can't be matched
+            let is_numeric = column_selector.start_regex.is_none()
+                && column_selector.end_regex.is_none()
+                && column_selector.step == 1
+                && column_selector.modulo.is_none()
+                && column_selector.filter.is_none()
+                && column_selector.start_idx >= 0
+                && column_selector.end_idx >= 0;
+            if !is_numeric {
+                exit_with_ock_error(error::OckError::Format(format!(
+                    "--cut-compat only supports positive column indices and ranges, not '{}'",
+                    column_selector.raw
+                )));
+            }
+            if column_selector.start_idx > column_selector.end_idx {
+                exit_with_ock_error(error::OckError::Format(format!(
+                    "--cut-compat can't use the decreasing range '{}'",
+                    column_selector.raw
+                )));
+            }
+            ranges.push((column_selector.start_idx as usize, column_selector.end_idx as usize));
+        }
+        return Some(ranges);
+    }
+    let rows_given = !args.rows.is_empty()
+        || args.rows_file.is_some()
+        || args.first
+        || args.last
+        || args.from.is_some()
+        || args.to.is_some();
+    // Acting like cut only makes sense for a literal delimiter given with -d, and when nothing
+    // else needs the row split into cells the usual way
+    let changes_cells = args.trim
+        || args.fill.is_some()
+        || args.join.is_some()
+        || args.collapse_whitespace
+        || args.preserve_spacing
+        || args.align_by_index
+        || !args.column_order.is_empty();
+    let needs_header = args.output_format != cli::OutputFormat::Text
+        || args.tsv
+        || args.vertical
+        || args.header_only
+        || !args.conditions.is_empty();
+    if args.column_delimiter == r"\s"
+        || args.column_delimiter_from_env
+        || args.auto_delimiter
+        || !select_options.column_delimiter_is_literal
+        || changes_cells
+        || needs_header
+        || rows_given
+        || args.invert_columns
+        || args.per_row_indices
+        || args.delimiter_preserve
+        || args.fixed_width.is_some()
+        || args.csv_input
+        || args.print_indices
+        || args.strict
     {
-        // Sequence started
-        in_sequence = true;
-        selector.start_idx = item_idx;
-        if (utils::regex_eq(&selector.end_regex, &selector.start_regex)
-            && !utils::regex_is_default(&selector.start_regex))
-            || (selector.end_idx == selector.start_idx)
+        return None;
+    }
+    match column_selectors {
+        [column_selector]
+            if column_selector.start_idx == column_selector.end_idx
+                && column_selector.start_idx >= 0
+                && column_selector.start_regex.is_none()
+                && column_selector.filter.is_none() =>
         {
-            // Only one column selected
-            selector.stopped = true;
-        }
-    } else if item_idx == selector.end_idx || selector.end_regex.is_match(item) {
-        // Sequence end
-        in_sequence = true;
-        selector.end_idx = item_idx;
-    } else if item_idx > selector.start_idx
-        && item_idx < selector.end_idx
-        && (item_idx - selector.start_idx) % selector.step == 0
-    {
-        // Sequence middle
-        in_sequence = true;
+            let field_idx = column_selector.start_idx as usize;
+            Some(vec![(field_idx, field_idx)])
+        }
+        _ => None,
     }
-    in_sequence
 }
 
-/// Get vector of columns to use from header row
+/// Get fields from a row the way `cut` does, keeping empty fields so they count towards the index.
+/// Fields are output in input order, each only once, joined by `joiner`. Rows without the delimiter
+/// are returned whole.
+fn cut_fields(
+    row: &str,
+    field_ranges: &[(usize, usize)],
+    column_delimiter: &Regex,
+    joiner: &str,
+) -> String {
+    let fields = utils::split_by_keep_empty(row, column_delimiter);
+    if fields.len() == 1 {
+        return row.to_string();
+    }
+    fields
+        .iter()
+        .enumerate()
+        .filter(|(field_idx, _)| {
+            field_ranges.iter().any(|&(start, end)| (start..=end).contains(field_idx))
+        })
+        .map(|(_, field)| field.as_str())
+        .collect::<Vec<&str>>()
+        .join(joiner)
+}
+
+/// Describe how a selector was parsed, for --explain
+fn describe_selector(selector: &selector::Selector) -> String {
+    let describe_bound = |idx: i64, regex: &Option<Regex>| match regex {
+        Some(regex) => format!("regex /{}/", regex.as_str()),
+        None if idx == i64::MAX => String::from("last"),
+        None => format!("index {}", idx),
+    };
+    let index_based = selector.start_regex.is_none()
+        && selector.end_regex.is_none();
+    let mut description = format!(
+        "'{}' is {}, start {}, end {}, step {}",
+        selector.raw,
+        if index_based { "index-based" } else { "regex-based" },
+        describe_bound(selector.start_idx, &selector.start_regex),
+        describe_bound(selector.end_idx, &selector.end_regex),
+        selector.step
+    );
+    if let Some((modulus, remainder)) = selector.modulo {
+        let raw = &selector.raw;
+        return format!("'{}' is modulo-based, 0-based index % {} == {}", raw, modulus, remainder);
+    }
+    if let Some(occurrence) = selector.occurrence {
+        description.push_str(&format!(", match {} only", occurrence));
+    }
+    if let Some(filter) = &selector.filter {
+        description.push_str(&format!(", only where /{}/ matches", filter.as_str()));
+    }
+    description
+}
+
+/// Get vector of columns to use from header row, along with how many columns each selector matched
+/// If `invert` is set, the columns that weren't matched are used instead. Negative indices count
+/// back from `column_count` if given (e.g. a ragged row's length), otherwise from the header's.
 fn get_columns(
-    index_row: &String,
-    column_selectors: &mut Vec<selector::Selector>,
-    column_delimiter: &String,
+    index_row: &str,
+    column_selectors: &mut [selector::Selector],
+    column_splitter: &splitter::ColumnSplitter,
+    invert: bool,
+    column_count: Option<usize>,
+) -> (Vec<usize>, Vec<usize>) {
+    // Return a vector of column indices to export
+    let mut export_column_idxs: Vec<usize> = Vec::new();
+    let mut match_counts: Vec<usize> = vec![0; column_selectors.len()];
+    let columns = column_splitter.split(index_row);
+    let column_count = column_count.unwrap_or(columns.len());
+    // Negative indices and anchors are relative to the columns, so resolve them now they're known
+    for column_selector in column_selectors.iter_mut() {
+        column_selector.resolve_indices(column_count);
+        column_selector.resolve_anchors(&columns);
+    }
+    // Iterate through columns, matching regexes against the header's cells (if it has them)
+    for col_idx in 0..column_count {
+        let column = columns.get(col_idx).map_or("", |column| column.as_str());
+        // Iterate through selector in vector of selectors
+        for (selector_idx, column_selector) in column_selectors.iter_mut().enumerate() {
+            if column_selector.matches(col_idx, column, column_count) {
+                export_column_idxs.push(col_idx);
+                match_counts[selector_idx] += 1;
+            }
+        }
+    }
+    if invert {
+        export_column_idxs = (0..column_count)
+            .filter(|col_idx| !export_column_idxs.contains(col_idx))
+            .collect();
+    }
+    // Return indexes of matched columns
+    (export_column_idxs, match_counts)
+}
+
+/// Get the columns with a cell matching `cell_regex` in any of the given rows
+/// If `invert` is set, the columns without a matching cell are used instead
+fn cell_regex_columns(
+    rows: &[String],
+    cell_regex: &Regex,
+    column_splitter: &splitter::ColumnSplitter,
+    invert: bool,
 ) -> Vec<usize> {
-    if column_selectors.len() == 0 {
-        // Return blank vector if no column selectors present
-        Vec::new()
-    } else {
-        // Return a vector of column indices to export
-        let mut export_column_idxs: Vec<usize> = Vec::new();
-        // Iterate through columns in first row
-        for (col_idx, column) in utils::split(index_row, column_delimiter).iter().enumerate() {
-            // Iterate through selector in vector of selectors
-            for column_selector in column_selectors.iter_mut() {
-                if item_in_sequence(col_idx, column, column_selector) {
-                    export_column_idxs.push(col_idx);
-                }
+    let mut column_count = 0;
+    let mut matched_columns: BTreeSet<usize> = BTreeSet::new();
+    for row in rows {
+        let cells = column_splitter.split(row);
+        column_count = column_count.max(cells.len());
+        for (col_idx, cell) in cells.iter().enumerate() {
+            if cell_regex.is_match(cell) {
+                matched_columns.insert(col_idx);
             }
         }
-        // Return indexes of matched columns
-        export_column_idxs
+    }
+    if invert {
+        (0..column_count).filter(|col_idx| !matched_columns.contains(col_idx)).collect()
+    } else {
+        matched_columns.into_iter().collect()
     }
 }
 
+/// Grab a run of consecutive cells as one, sliced straight from the row so the original spacing
+/// between them is kept. Expects `cells_to_select` in ascending order, as `get_columns` gives them.
+fn get_cells_preserving_spacing(
+    row: &str,
+    cells_to_select: &[usize],
+    column_splitter: &splitter::ColumnSplitter,
+) -> Vec<String> {
+    let offsets = column_splitter.offsets(row);
+    // Group selected cells into runs of consecutive (first, last) indices
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for &cell_idx in cells_to_select.iter().filter(|&&idx| idx < offsets.len()) {
+        match runs.last_mut() {
+            Some((_, last)) if *last == cell_idx => {}
+            Some((_, last)) if *last + 1 == cell_idx => *last = cell_idx,
+            _ => runs.push((cell_idx, cell_idx)),
+        }
+    }
+    runs.iter()
+        .map(|&(first, last)| row[offsets[first].0..offsets[last].1].to_string())
+        .collect()
+}
+
+/// Options changing how cells are grabbed from a row
+struct CellOptions<'a> {
+    /// Strip leading and trailing whitespace from each cell
+    trim: bool,
+
+    /// Squeeze runs of whitespace inside each cell down to a single space
+    collapse_whitespace: bool,
+
+    /// Placeholder for selected cells past the end of a short row
+    fill: Option<&'a str>,
+
+    /// Keep runs of consecutive cells together, with their original spacing
+    preserve_spacing: bool,
+
+    /// Keep the delimiter after each cell on the end of it
+    delimiter_preserve: bool,
+
+    /// Rearrange the selected cells by their (1-based) positions, unless empty
+    order: &'a [usize],
+
+    /// Join the selected cells into one, separated by this
+    join: Option<&'a str>,
+
+    /// Place cells by their index in the row rather than one after another
+    align_by_index: bool,
+}
+
+/// Grab the selected cells in a row along with their indices, with placeholders for selected cells
+/// past the end of a short row if there's a fill
+fn get_indexed_cells(
+    row: &str,
+    cells_to_select: &[usize],
+    column_splitter: &splitter::ColumnSplitter,
+    options: &CellOptions,
+) -> Vec<(usize, String)> {
+    let cells = if options.delimiter_preserve {
+        column_splitter.split_preserving(row)
+    } else {
+        column_splitter.split(row)
+    };
+    // Mark selected cells up front rather than searching the selection for every cell, which
+    // gets slow for rows with thousands of cells
+    let row_len = cells.len();
+    let mut is_selected = vec![false; row_len];
+    for &cell_idx in cells_to_select.iter().filter(|&&idx| idx < row_len) {
+        is_selected[cell_idx] = true;
+    }
+    let mut indexed_cells: Vec<(usize, String)> = cells
+        .into_iter()
+        .enumerate()
+        .zip(is_selected)
+        .filter(|(_, selected)| *selected)
+        .map(|(indexed_cell, _)| indexed_cell)
+        .collect();
+    // Stand in for selected cells past the end of a short row, so every row has the same arity
+    if let Some(fill) = options.fill {
+        let mut missing_idxs: Vec<usize> =
+            cells_to_select.iter().copied().filter(|&idx| idx >= row_len).collect();
+        missing_idxs.dedup();
+        indexed_cells.extend(missing_idxs.into_iter().map(|idx| (idx, fill.to_string())));
+    }
+    indexed_cells
+}
+
 /// Grab cells in a row by a list of given indeces
-fn get_cells(row: &String, cells_to_select: &Vec<usize>, column_delimiter: &String) -> Vec<String> {
-    if cells_to_select.len() == 0 {
+fn get_cells(
+    row: &str,
+    cells_to_select: &[usize],
+    column_splitter: &splitter::ColumnSplitter,
+    options: &CellOptions,
+) -> Vec<String> {
+    let mut output: Vec<String> = Vec::new();
+    if cells_to_select.is_empty() {
         // If no cells to select specified, return one element vector of the row
-        vec![(*row).clone()]
-    } else {
-        // Iterate through cells in row and push ones with matching indeces to output vector
-        let mut output: Vec<String> = Vec::new();
-        for (cell_idx, cell) in utils::split(row, column_delimiter).iter().enumerate() {
-            if cells_to_select.contains(&cell_idx) {
-                output.push((*cell).clone());
+        output.push(row.to_string());
+    } else if options.preserve_spacing {
+        output = get_cells_preserving_spacing(row, cells_to_select, column_splitter);
+    } else if options.align_by_index {
+        // Put each cell at its index in the row, leaving the columns between them empty. Columns
+        // empty in every row take up no space when aligned, so each cell lines up with the others
+        // from its column even when rows are ragged
+        for (cell_idx, cell) in get_indexed_cells(row, cells_to_select, column_splitter, options) {
+            if output.len() <= cell_idx {
+                output.resize(cell_idx + 1, String::new());
             }
+            output[cell_idx] = cell;
         }
+    } else {
+        output = get_indexed_cells(row, cells_to_select, column_splitter, options)
+            .into_iter()
+            .map(|(_, cell)| cell)
+            .collect();
+    }
+    if options.trim {
+        // Strip surrounding whitespace only after splitting, so column detection is unaffected
+        output = output.iter().map(|cell| cell.trim().to_string()).collect();
+    }
+    if options.collapse_whitespace {
+        output = output.iter().map(|cell| utils::collapse_whitespace(cell)).collect();
+    }
+    if !options.order.is_empty() {
+        output = reorder_cells(&output, options.order);
+    }
+    if let Some(separator) = options.join {
+        output = vec![output.join(separator)];
+    }
+    output
+}
+
+/// Rearrange cells by their (1-based) positions, which can repeat or leave cells out. Positions
+/// past the end of a short row give empty cells.
+fn reorder_cells(cells: &[String], order: &[usize]) -> Vec<String> {
+    order.iter().map(|&position| cells.get(position - 1).cloned().unwrap_or_default()).collect()
+}
+
+/// Options changing how cells are lined up into columns
+#[derive(Default)]
+struct AlignOptions {
+    /// Leave out empty cells so they don't leave runs of padding behind
+    squeeze: bool,
+
+    /// Narrowest a column is padded to
+    min_width: usize,
+
+    /// Character to pad cells with, rather than a space
+    pad_char: Option<char>,
+
+    /// Show tabs, carriage returns, and other non-printable characters in cells as escapes
+    escape: bool,
+
+    /// Count every character as one column wide, rather than measuring wide characters
+    ascii_width: bool,
+
+    /// Put commas between groups of three digits in cells that are integers
+    group_digits: bool,
+}
+
+/// Align cells into columns for pretty printing, returning one formatted String per row
+fn format_columns(output: &[Vec<String>], options: &AlignOptions) -> Vec<String> {
+    // Escape and group digits in cells before measuring them, so columns are as wide as what's
+    // actually printed
+    let escaped_output: Vec<Vec<String>>;
+    let output = if options.escape || options.group_digits {
+        let format_cell = |cell: &String| {
+            let cell = if options.group_digits { utils::group_digits(cell) } else { cell.clone() };
+            if options.escape {
+                output::escape_nonprintable(&cell)
+            } else {
+                cell
+            }
+        };
+        escaped_output = output.iter().map(|row| row.iter().map(format_cell).collect()).collect();
+        &escaped_output
+    } else {
         output
+    };
+    // Measure cells by the columns they take up on the terminal, unless told every character
+    // takes one
+    let cell_width = |cell: &str| {
+        if options.ascii_width {
+            cell.chars().count()
+        } else {
+            utils::display_width(cell)
+        }
+    };
+    // Iterate through results and find max length of each column, sized up front for the widest
+    // row rather than growing as columns are found
+    let column_count = output.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut max_column_lengths: Vec<usize> = vec![0; column_count];
+    for row in output {
+        for (max_length, cell) in max_column_lengths.iter_mut().zip(row) {
+            *max_length = (*max_length).max(cell_width(cell));
+        }
+    }
+
+    // Pad each cell to the width of its column. Padded rows are all about as wide as the columns
+    // put together, so each row is allocated at that size up front rather than growing
+    let row_width: usize =
+        max_column_lengths.iter().map(|&length| length.max(options.min_width) + 2).sum();
+    let mut formatted_rows: Vec<String> = Vec::with_capacity(output.len());
+    for row in output {
+        let mut formatted_row = String::with_capacity(row_width);
+        // Columns empty in every row have no width, and are left out rather than padded
+        let is_skipped = |idx: usize, cell: &String| {
+            max_column_lengths[idx] == 0 || (options.squeeze && cell.is_empty())
+        };
+        // Skipped and empty cells print nothing, so the last cell with something to print is left
+        // unpadded, and any empty cells after it are left off
+        let last_idx = (0..row.len())
+            .rposition(|idx| !is_skipped(idx, &row[idx]) && !row[idx].is_empty())
+            .unwrap_or(0);
+        for (idx, cell) in row.iter().enumerate() {
+            if idx > last_idx || is_skipped(idx, cell) {
+                continue;
+            }
+            if idx == last_idx {
+                // Don't pad the last cell, so no trailing whitespace ends up before the delimiter
+                formatted_row.push_str(cell);
+                continue;
+            }
+            let width = max_column_lengths[idx].max(options.min_width) + 2;
+            let padding = width.saturating_sub(cell_width(cell));
+            formatted_row.push_str(cell);
+            formatted_row.extend(std::iter::repeat_n(options.pad_char.unwrap_or(' '), padding));
+        }
+        formatted_rows.push(formatted_row);
+    }
+    formatted_rows
+}
+
+/// Compare two cells as numbers, falling back to comparing them as text when either isn't one.
+/// Numbers come before anything that isn't a number.
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// Parse the cells of a column (1-based) as numbers, letting the user know how many weren't
+fn column_numbers<'a>(rows: impl Iterator<Item = &'a Vec<String>>, column: usize) -> Vec<f64> {
+    let mut numbers: Vec<f64> = Vec::new();
+    let mut skipped_cells = 0;
+    for row in rows {
+        match row.get(column - 1).and_then(|cell| cell.trim().parse::<f64>().ok()) {
+            Some(number) => numbers.push(number),
+            None => skipped_cells += 1,
+        }
+    }
+    if skipped_cells > 0 {
+        eprintln!(
+            "warning: skipped {} cells in column {} that aren't numbers",
+            skipped_cells, column
+        );
+    }
+    numbers
+}
+
+/// Guess the column delimiter from a sample of rows, picking whichever of a comma, a tab, or
+/// whitespace splits the most of them into the same number of (more than one) columns. Ties go
+/// to the comma and then the tab, as whitespace splits most text into several columns.
+fn detect_delimiter(sample: &[&str]) -> &'static str {
+    let mut best = (r"\s", 0);
+    for delimiter in [",", r"\t", r"\s"] {
+        let column_counts: Vec<usize> = sample
+            .iter()
+            .map(|row| match delimiter {
+                r"\s" => row.split_whitespace().count(),
+                delimiter => row.split(utils::unescape(delimiter).as_str()).count(),
+            })
+            .collect();
+        // Rows agreeing with the most common column count, as long as that's more than one column
+        let agreeing = column_counts
+            .iter()
+            .filter(|&&count| count > 1)
+            .map(|count| column_counts.iter().filter(|&other| other == count).count())
+            .max()
+            .unwrap_or(0);
+        if agreeing > best.1 {
+            best = (delimiter, agreeing);
+        }
+    }
+    best.0
+}
+
+/// Common delimiter in the header row, when the column delimiter didn't split it at all, as a
+/// likely sign the wrong delimiter was used
+fn delimiter_hint(
+    header_row: &str,
+    column_splitter: &splitter::ColumnSplitter,
+) -> Option<&'static str> {
+    if !matches!(column_splitter, splitter::ColumnSplitter::Delimiter(_))
+        || column_splitter.split(header_row).len() != 1
+    {
+        return None;
+    }
+    // Shown as they'd be passed to --column-delimiter
+    [(',', ","), ('\t', r"\t"), (';', ";"), ('|', "|")]
+        .into_iter()
+        .find(|&(delimiter, _)| header_row.contains(delimiter))
+        .map(|(_, shown)| shown)
+}
+
+/// Print how long a step took to stderr, for --timing
+fn report_timing(enabled: bool, step: &str, started: Instant) {
+    if enabled {
+        eprintln!("timing: {} took {:?}", step, started.elapsed());
+    }
+}
+
+/// Number of rows the terminal on stdout has, asking it with the `TIOCGWINSZ` ioctl
+#[cfg(unix)]
+fn terminal_rows() -> Option<usize> {
+    use std::os::fd::AsRawFd;
+
+    let mut size = libc::winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    // SAFETY: TIOCGWINSZ only writes a winsize through the pointer, which points at one
+    let result = unsafe { libc::ioctl(io::stdout().as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+    (result == 0 && size.ws_row > 0).then_some(size.ws_row as usize)
+}
+
+#[cfg(not(unix))]
+fn terminal_rows() -> Option<usize> {
+    None
+}
+
+/// Number of lines the terminal has room for, asking the terminal itself, then falling back to the
+/// `LINES` environment variable, or 24 if neither says
+fn terminal_height() -> usize {
+    terminal_rows()
+        .or_else(|| std::env::var("LINES").ok().and_then(|lines| lines.parse::<usize>().ok()))
+        .filter(|&lines| lines > 0)
+        .unwrap_or(24)
+}
+
+/// Warn about a selector that didn't select what it asked for, or exit with an error under --strict
+fn warn_or_fail(strict: bool, message: String) {
+    if strict {
+        exit_with_ock_error(error::OckError::Strict(message));
+    }
+    eprintln!("warning: {}", message);
+}
+
+/// Check whether any numeric index of a selector that hasn't been resolved yet falls outside `len`
+/// rows or columns, for --strict
+fn out_of_bounds(selector: &selector::Selector, len: usize) -> bool {
+    let len = len as i64;
+    let in_bounds = |idx: i64| (-len..len).contains(&idx);
+    let start_is_index =
+        selector.start_regex.is_none() && selector.start_offset.is_none();
+    let end_is_index = selector.end_regex.is_none()
+        && selector.end_offset.is_none()
+        && selector.end_idx != i64::MAX;
+    (start_is_index && !in_bounds(selector.start_idx))
+        || (end_is_index && !in_bounds(selector.end_idx))
+}
+
+/// Print an error to stderr and exit with the code for its kind
+fn exit_with_ock_error(error: error::OckError) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(error.exit_code())
+}
+
+/// Exit with a format error from an option that isn't valid, saying which option it was in
+fn exit_with_option_error(option: &str, error: impl std::fmt::Display) -> ! {
+    exit_with_ock_error(error::OckError::Format(format!("invalid {}: {}", option, error)))
+}
+
+/// Like `exit_with_ock_error`, but saying whether a selector error came from row or column
+/// selectors, given as `kind`
+fn exit_with_selector_error(error: error::OckError, kind: &str) -> ! {
+    match &error {
+        error::OckError::Selector(_) => {
+            eprintln!("error: in {} {}", kind, error);
+            std::process::exit(error.exit_code())
+        }
+        error::OckError::Io { .. }
+        | error::OckError::Format(_)
+        | error::OckError::Strict(_) => exit_with_ock_error(error),
+    }
+}
+
+/// Parse each inline selector list and any read from a selector file into one vector
+/// Inline selectors come first, in the order given, followed by those from the file
+fn collect_selectors(
+    inline: &[String],
+    file: &Option<String>,
+    options: &selector::ParseOptions,
+) -> Result<Vec<selector::Selector>, error::OckError> {
+    let mut selectors: Vec<selector::Selector> = Vec::new();
+    for selector_list in inline {
+        let parsed = selector::parse_selectors(selector_list, options)
+            .map_err(|e| e.offset_position(selectors.len()))?;
+        selectors.extend(parsed);
+    }
+    if let Some(path) = file {
+        selectors.extend(selector::parse_selectors_file(path, options)?);
+    }
+    // Fall back to selecting everything if no selectors were given
+    if selectors.is_empty() {
+        selectors.push(selector::Selector::default());
+    }
+    Ok(selectors)
+}
+
+/// How input is split up and selectors are read, worked out from the arguments in one place
+struct SelectOptions {
+    /// Options for parsing row and column selectors
+    parse: selector::ParseOptions,
+
+    /// Regex pattern splitting input into rows
+    row_delimiter: String,
+
+    /// Regex pattern splitting rows into cells
+    column_delimiter: String,
+
+    /// Whether the column delimiter is plain text rather than a regex, so it can act like cut
+    column_delimiter_is_literal: bool,
+
+    /// Text separating fields when acting like cut, which takes the delimiter literally and
+    /// defaults to a tab
+    cut_delimiter: String,
+
+    /// Character separating CSV fields, a comma unless another single character is given, or None
+    /// if the delimiter given is longer
+    csv_delimiter: Option<char>,
+
+    /// Whether rows are lines, split on a plain newline
+    rows_are_lines: bool,
+}
+
+impl SelectOptions {
+    fn from_args(args: &cli::Args) -> SelectOptions {
+        let column_delimiter = utils::unescape(&args.column_delimiter);
+        SelectOptions {
+            parse: selector::ParseOptions {
+                zero_based: args.zero_based,
+                case_sensitive: args.case_sensitive,
+                exclusive_end: args.exclusive_end,
+                repeat_ranges: args.repeat_ranges,
+                glob: args.glob,
+                regex_flags: args.regex_flags.clone().unwrap_or_default(),
+            },
+            // Paragraphs are separated by one or more blank lines, which can have spaces in them
+            row_delimiter: if args.paragraph {
+                String::from(r"\n\s*\n")
+            } else {
+                utils::decode_delimiter(&args.row_delimiter, args.row_delimiter_literal)
+            },
+            column_delimiter: utils::decode_delimiter(&args.column_delimiter, args.fixed_strings),
+            column_delimiter_is_literal: args.fixed_strings
+                || regex::escape(&column_delimiter) == column_delimiter,
+            cut_delimiter: if args.cut_compat && args.column_delimiter == r"\s" {
+                String::from("\t")
+            } else {
+                column_delimiter.clone()
+            },
+            csv_delimiter: match column_delimiter.as_str() {
+                r"\s" | " " => Some(','),
+                delimiter if delimiter.chars().count() == 1 => delimiter.chars().next(),
+                _ => None,
+            },
+            rows_are_lines: SelectOptions::rows_are_lines(args),
+        }
+    }
+
+    /// Whether rows are lines, split on a plain newline, which is known before the rest of the
+    /// options are (e.g. before the column delimiter is detected)
+    fn rows_are_lines(args: &cli::Args) -> bool {
+        !args.paragraph && utils::unescape(&args.row_delimiter) == "\n"
     }
 }
 
 fn main() {
     // Parse arguments
-    let args = cli::Args::parse();
-    let input = cli::parse_input(&args.input);
+    let mut args = cli::Args::parse_with_env();
+    let started = Instant::now();
+    // Only the header is needed to list columns, so don't wait on the rest of stdin, as long as
+    // rows are lines. Quoted CSV fields can hold newlines, and JSON's header comes from every
+    // record, so both are read whole.
+    let header_in_first_lines = !args.csv_input
+        && args.input_format != cli::InputFormat::Json
+        && SelectOptions::rows_are_lines(&args);
+    let mut input = if args.list_columns && args.input.is_empty() && header_in_first_lines {
+        cli::read_stdin_lines(args.skip + args.header_row)
+    } else if args.stdin {
+        cli::parse_input_after_stdin(&args.input)
+    } else {
+        cli::parse_input(&args.input)
+    }
+    .unwrap_or_else(|e| exit_with_ock_error(e));
+    report_timing(args.timing, "reading input", started);
+    if args.input_format == cli::InputFormat::Json {
+        // Records are flattened into CSV, which is then read like any other
+        input = input::json_to_csv(&input).unwrap_or_else(|e| {
+            exit_with_ock_error(error::OckError::Format(format!("invalid JSON input: {}", e)))
+        });
+        args.csv_input = true;
+    }
+    if let Some(tab_width) = args.expand_tabs {
+        // Swap tabs for spaces before anything is split, so whitespace is consistent
+        input = utils::expand_tabs(&input, tab_width);
+    }
+    if args.auto_delimiter {
+        // Sample the first few rows after any skipped ones, as banners rarely share the delimiter
+        let sample: Vec<&str> =
+            input.lines().filter(|line| !line.is_empty()).skip(args.skip).take(10).collect();
+        args.column_delimiter = detect_delimiter(&sample).to_string();
+        if args.verbose {
+            eprintln!("detected column delimiter '{}'", args.column_delimiter);
+        }
+    }
 
     // Parse selectors
-    let mut row_selectors = selector::parse_selectors(&args.rows);
-    let mut column_selectors = selector::parse_selectors(&args.columns);
+    let select_options = SelectOptions::from_args(&args);
+    let parse_options = &select_options.parse;
+    let mut row_selectors = collect_selectors(&args.rows, &args.rows_file, parse_options)
+        .unwrap_or_else(|e| exit_with_selector_error(e, "row"));
+    if args.first || args.last {
+        // Shorthand for the first and/or last row, built directly so --zero-based doesn't apply
+        row_selectors.clear();
+        if args.first {
+            row_selectors.push(selector::Selector {
+                raw: String::from("--first"),
+                start_idx: 0,
+                end_idx: 0,
+                ..selector::Selector::default()
+            });
+        }
+        if args.last {
+            row_selectors.push(selector::Selector {
+                raw: String::from("--last"),
+                start_idx: -1,
+                end_idx: -1,
+                ..selector::Selector::default()
+            });
+        }
+    }
+    if args.from.is_some() || args.to.is_some() {
+        // The range is added to any other row selectors, but replaces the fallback of selecting
+        // everything when there aren't any
+        if row_selectors.iter().all(|row_selector| row_selector.raw.is_empty()) {
+            row_selectors.clear();
+        }
+        let range =
+            selector::regex_range(args.from.as_deref(), args.to.as_deref(), parse_options)
+                .unwrap_or_else(|e| exit_with_selector_error(e.into(), "row"));
+        row_selectors.push(range);
+    }
+    let mut column_selectors = collect_selectors(&args.columns, &args.columns_file, parse_options)
+        .unwrap_or_else(|e| exit_with_selector_error(e, "column"));
+
+    // Like cut, the delimiter is literal in cut mode
+    let cut_field_ranges = cut_field_ranges(&args, &select_options, &column_selectors);
+    let cut_joiner = &select_options.cut_delimiter;
+    let cut_delimiter = Regex::new(&regex::escape(cut_joiner))
+        .unwrap_or_else(|e| exit_with_option_error("column delimiter", e));
+
+    // Compile the column delimiter once, rather than for every row it splits
+    let column_delimiter = Regex::new(&select_options.column_delimiter)
+        .unwrap_or_else(|e| exit_with_option_error("column delimiter", e));
+    let column_splitter = if let Some(spec) = &args.fixed_width {
+        splitter::ColumnSplitter::FixedWidth(
+            splitter::parse_column_ranges(spec)
+                .unwrap_or_else(|e| exit_with_option_error("--fixed-width", e)),
+        )
+    } else if args.csv_input {
+        // CSV is comma-separated unless another delimiter is given, which has to be one character
+        let delimiter = select_options.csv_delimiter.unwrap_or_else(|| {
+            exit_with_ock_error(error::OckError::Format(format!(
+                "--csv-input needs a single character delimiter, not '{}'",
+                utils::unescape(&args.column_delimiter)
+            )))
+        });
+        splitter::ColumnSplitter::Csv(delimiter)
+    } else {
+        splitter::ColumnSplitter::Delimiter(column_delimiter)
+    };
 
     // Parse input data according to arguments
     let mut export_cols: Vec<usize> = Vec::new();
     let mut output: Vec<Vec<String>> = Vec::new();
-    let split_rows = utils::split(&input, &args.row_delimiter);
-    for (row_idx, row) in split_rows.iter().enumerate() {
-        if row_idx == 0 {
-            export_cols = get_columns(row, &mut column_selectors, &args.column_delimiter);
+    let row_delimiter = &select_options.row_delimiter;
+    let started = Instant::now();
+    let mut split_rows = if args.csv_input {
+        // Quoted CSV fields can have newlines in them, which don't end the record
+        splitter::split_csv_records(&input)
+    } else if args.group_by_blank {
+        // Blank rows separate the tables, so they're kept until each row's table is worked out
+        let row_delimiter = selector::get_or_compile_regex(row_delimiter)
+            .unwrap_or_else(|e| exit_with_option_error("row delimiter", e));
+        utils::split_by_keep_empty(&input, &row_delimiter)
+    } else if cut_field_ranges.is_some() {
+        // Like cut, blank rows are kept, other than the one after the last row delimiter
+        let row_delimiter = selector::get_or_compile_regex(row_delimiter)
+            .unwrap_or_else(|e| exit_with_option_error("row delimiter", e));
+        let mut rows = utils::split_by_keep_empty(&input, &row_delimiter);
+        if rows.last().is_some_and(|row| row.is_empty()) {
+            rows.pop();
         }
-        for row_selector in row_selectors.iter_mut() {
-            if item_in_sequence(row_idx, row, row_selector) {
-                output.push(get_cells(row, &export_cols, &args.column_delimiter));
+        rows
+    } else {
+        utils::split(&input, row_delimiter)
+            .unwrap_or_else(|e| exit_with_option_error("row delimiter", e))
+    };
+    report_timing(args.timing, "splitting rows", started);
+    if select_options.rows_are_lines && !args.keep_cr {
+        // Strip the "\r" left behind by CRLF line endings so it doesn't end up in the last column
+        for row in split_rows.iter_mut() {
+            if row.ends_with('\r') {
+                row.pop();
+            }
+        }
+    }
+    if args.paragraph {
+        // Drop the line breaks left at either end of the input, keeping the ones within paragraphs
+        for row in split_rows.iter_mut() {
+            *row = row.trim_matches(['\r', '\n']).to_string();
+            if !args.keep_cr && row.contains('\r') {
+                // Lines within a paragraph end in CRLF too, so strip those the same way
+                *row = row.replace("\r\n", "\n");
             }
         }
+        split_rows.retain(|row| !row.is_empty());
     }
 
-    // Iterate through results and find max length of each column for pretty printing 
-    let mut max_column_lengths: Vec<usize> = output[0].iter().map(|s| s.len()).collect();
-    for row in &output {
-        for (idx, cell) in row.iter().enumerate() {
-            let cell_length = cell.len();
-            if cell_length > max_column_lengths[idx] {
-                max_column_lengths[idx] = cell_length;
+    // Number the blank-row separated tables each row belongs to, then drop the blank rows
+    let mut row_tables: Vec<usize> = Vec::new();
+    if args.group_by_blank {
+        let mut table_idx = 0;
+        let mut table_has_rows = false;
+        split_rows.retain(|row| {
+            if row.trim().is_empty() {
+                if table_has_rows {
+                    table_idx += 1;
+                    table_has_rows = false;
+                }
+                return false;
+            }
+            row_tables.push(table_idx);
+            table_has_rows = true;
+            true
+        });
+    }
+
+    // Drop leading rows (e.g. banners) before any selection, so indices count from after them
+    split_rows.drain(..args.skip.min(split_rows.len()));
+    row_tables.drain(..args.skip.min(row_tables.len()));
+    // First row of each table, which is its header
+    let table_headers: Vec<usize> = (0..row_tables.len())
+        .filter(|&row_idx| row_idx == 0 || row_tables[row_idx] != row_tables[row_idx - 1])
+        .collect();
+
+    // Row used to match column selectors against, which isn't always the first. With --no-header
+    // it's only used to count columns, and is output as data like any other row
+    let header_idx = args.header_row - 1;
+    let header_row = split_rows.get(header_idx);
+    let has_header = !args.no_header;
+
+    // Print a numbered legend of the header row's columns and exit
+    if args.list_columns {
+        if let Some(header_row) = header_row {
+            let legend: Vec<String> = column_splitter.split(header_row)
+                .iter()
+                .enumerate()
+                .map(|(col_idx, column)| format!("{}: {}", col_idx + 1, column))
+                .collect();
+            println!("{}", format_columns(&[legend], &AlignOptions::default())[0]);
+        }
+        return;
+    }
+
+    // Print how many columns each row splits into, from the header on, to help spot ragged rows
+    // or the wrong delimiter
+    if args.count_columns {
+        for row in split_rows.iter().skip(header_idx) {
+            println!("{}", column_splitter.split(row).len());
+        }
+        return;
+    }
+
+    let rows_given = !args.rows.is_empty()
+        || args.rows_file.is_some()
+        || args.first
+        || args.last
+        || args.from.is_some()
+        || args.to.is_some();
+    let columns_given = !args.columns.is_empty() || args.columns_file.is_some();
+    // Negative row indices count back from the last row, and anchors are relative to the rows they
+    // match, so resolve both against the rows
+    for row_selector in row_selectors.iter_mut() {
+        let row_count = split_rows.len();
+        if args.strict && !row_selector.raw.is_empty() && out_of_bounds(row_selector, row_count) {
+            exit_with_ock_error(error::OckError::Strict(format!(
+                "row selector '{}' is out of bounds for {} rows",
+                row_selector.raw, row_count
+            )));
+        }
+        row_selector.resolve_indices(row_count);
+        row_selector.resolve_anchors(&split_rows);
+    }
+
+    // Describe how selectors were parsed and what they match, instead of selecting anything
+    if args.explain {
+        println!("Indices are 0-based, with negative indices resolved");
+        for (selector_idx, row_selector) in row_selectors.iter().enumerate() {
+            println!("row selector #{}: {}", selector_idx + 1, describe_selector(row_selector));
+        }
+        let header_columns = match header_row {
+            Some(header_row) => column_splitter.split(header_row),
+            None => Vec::new(),
+        };
+        for (selector_idx, column_selector) in column_selectors.iter().enumerate() {
+            let mut resolved_selector = column_selector.clone();
+            resolved_selector.resolve_indices(header_columns.len());
+            resolved_selector.resolve_anchors(&header_columns);
+            let description = describe_selector(&resolved_selector);
+            println!("column selector #{}: {}", selector_idx + 1, description);
+            // Match each selector on its own, so it's clear which columns came from which
+            let (col_idxs, _) = get_columns(
+                header_row.map_or("", |row| row.as_str()),
+                &mut [column_selector.clone()],
+                &column_splitter,
+                false,
+                None,
+            );
+            let matches: Vec<String> = col_idxs
+                .iter()
+                .map(|&col_idx| format!("{} ({})", col_idx, header_columns[col_idx]))
+                .collect();
+            println!("  matches columns: {}", matches.join(", "));
+        }
+        return;
+    }
+
+    // Negative column indices are resolved against each row with --per-row-indices, so keep a
+    // copy of the selectors from before they're resolved against the header
+    let unresolved_column_selectors = column_selectors.clone();
+
+    // Find which columns to export from the header row, unless each row has its own headers or
+    // ock is acting like cut, where there's no header
+    let match_header = !args.per_row_headers && !args.paragraph && cut_field_ranges.is_none();
+    // Numeric column selectors have to fit the header under --strict, or with each row having its
+    // own headers, the first row
+    if let Some(header_row) = header_row.filter(|_| args.strict && args.cell_regex.is_none()) {
+        let column_count = column_splitter.split(header_row).len();
+        for column_selector in column_selectors.iter().filter(|s| !s.raw.is_empty()) {
+            if out_of_bounds(column_selector, column_count) {
+                exit_with_ock_error(error::OckError::Strict(format!(
+                    "column selector '{}' is out of bounds for {} columns",
+                    column_selector.raw, column_count
+                )));
+            }
+        }
+    }
+    if let Some(pattern) = &args.cell_regex {
+        // Columns are picked by their contents rather than the header, so check every cell
+        let flags = if args.case_sensitive { "" } else { "(?i)" };
+        let cell_regex = selector::get_or_compile_regex(&format!("{}{}", flags, pattern))
+            .unwrap_or_else(|e| exit_with_option_error("--cell-regex", e));
+        let rows = &split_rows[header_idx.min(split_rows.len())..];
+        export_cols = cell_regex_columns(rows, &cell_regex, &column_splitter, args.invert_columns);
+    } else if let Some(header_row) = header_row.filter(|_| match_header) {
+        if args.no_header {
+            // Without header names, only numeric selectors mean anything
+            column_selectors.retain(|column_selector| {
+                let needs_names = column_selector.start_regex.is_some()
+                    || column_selector.end_regex.is_some()
+                    || column_selector.filter.is_some();
+                if needs_names {
+                    let message = format!(
+                        "column selector '{}' needs header names, which --no-header leaves out",
+                        column_selector.raw
+                    );
+                    warn_or_fail(args.strict, message);
+                }
+                !needs_names
+            });
+        }
+        if columns_given {
+            // Advisory only, for the easy mistake of splitting other data on whitespace
+            if let Some(delimiter) = delimiter_hint(header_row, &column_splitter) {
+                eprintln!(
+                    "hint: the header row wasn't split into columns, but contains '{}', try \
+                     --column-delimiter '{}'",
+                    delimiter, delimiter
+                );
+            }
+        }
+        let invert = args.invert_columns;
+        let match_counts;
+        (export_cols, match_counts) =
+            get_columns(header_row, &mut column_selectors, &column_splitter, invert, None);
+        // Let the user know about selectors that didn't match any columns, and with --stats, how
+        // many columns the others matched
+        for (column_selector, match_count) in column_selectors.iter().zip(match_counts) {
+            let raw = &column_selector.raw;
+            if raw.is_empty() {
+                continue;
+            }
+            if match_count == 0 {
+                warn_or_fail(args.strict, format!("column selector '{}' matched no columns", raw));
+            } else if args.stats {
+                let plural = if match_count == 1 { "" } else { "s" };
+                eprintln!("selector '{}' matched {} column{}", raw, match_count, plural);
+            }
+        }
+    }
+
+    // Print the 0-based indices of the selected columns for other tools to use, and exit
+    if args.print_indices {
+        if export_cols.is_empty() {
+            std::process::exit(1);
+        }
+        let indices: Vec<String> = export_cols.iter().map(|col_idx| col_idx.to_string()).collect();
+        println!("{}", indices.join(","));
+        return;
+    }
+
+    // Work out which column each --where condition checks, matching against the header like -c
+    let conditions: Vec<(usize, &condition::Condition)> = args
+        .conditions
+        .iter()
+        .map(|condition| {
+            let mut condition_selectors =
+                selector::parse_selectors(&condition.column, parse_options)
+                    .unwrap_or_else(|e| exit_with_selector_error(e.into(), "--where"));
+            let (col_idxs, _) = get_columns(
+                header_row.map_or("", |row| row.as_str()),
+                &mut condition_selectors,
+                &column_splitter,
+                false,
+                None,
+            );
+            match col_idxs.first() {
+                Some(&col_idx) => (col_idx, condition),
+                None => exit_with_ock_error(error::OckError::Format(format!(
+                    "--where column '{}' matched no columns",
+                    condition.column
+                ))),
+            }
+        })
+        .collect();
+
+    // Reordering can only pick from the columns that were selected
+    if match_header {
+        let past_end = args.column_order.iter().find(|&&position| position > export_cols.len());
+        if let Some(position) = past_end {
+            exit_with_ock_error(error::OckError::Format(format!(
+                "--column-order position {} is past the {} selected columns",
+                position,
+                export_cols.len()
+            )));
+        }
+    }
+
+    let join_separator = args.join.as_deref().map(utils::unescape);
+    let cell_options = CellOptions {
+        trim: args.trim,
+        collapse_whitespace: args.collapse_whitespace,
+        fill: args.fill.as_deref(),
+        preserve_spacing: args.preserve_spacing,
+        delimiter_preserve: args.delimiter_preserve,
+        order: &args.column_order,
+        join: join_separator.as_deref(),
+        // Only aligned text has columns for cells to line up in
+        align_by_index: args.align_by_index
+            && !args.tsv
+            && args.output_format == cli::OutputFormat::Text,
+    };
+    // Selected header cells, which structured output formats use as keys. With no columns to
+    // select there are none, rather than the whole header row that no columns would otherwise give
+    let no_columns_matched = (columns_given || args.invert_columns || args.cell_regex.is_some())
+        && export_cols.is_empty()
+        && match_header;
+    let header_cells: Vec<String> = match header_row.filter(|_| has_header && !no_columns_matched) {
+        Some(header_row) => get_cells(header_row, &export_cols, &column_splitter, &cell_options),
+        None => Vec::new(),
+    };
+
+    // Print just the header cells the column selectors pick, to check a selection before using it
+    if args.header_only {
+        if header_cells.is_empty() || (export_cols.is_empty() && columns_given) {
+            std::process::exit(1);
+        }
+        println!("{}", format_columns(&[header_cells], &AlignOptions::default())[0]);
+        return;
+    }
+
+    // Find which rows to export, skipping any preamble before the header unless rows were
+    // explicitly selected
+    let started = Instant::now();
+    let mut selected_rows: Vec<usize> = if no_columns_matched {
+        // None of the columns asked for exist, so there's nothing to output
+        Vec::new()
+    } else if let Some(row_idxs) =
+        numeric_row_indices(&row_selectors, split_rows.len(), args.preserve_order)
+    {
+        row_idxs
+            .into_iter()
+            .filter(|&row_idx| row_idx >= header_idx || rows_given)
+            .collect()
+    } else {
+        // Rows are kept with the selector that matched them, to sort by for --preserve-order
+        let mut row_idxs: Vec<(usize, usize)> = Vec::new();
+        let mut matched_selectors: Vec<bool> = vec![false; row_selectors.len()];
+        for (row_idx, row) in split_rows.iter().enumerate() {
+            if row_idx < header_idx && !rows_given {
+                continue;
+            }
+            // Every selector sees every row to keep its own state (like steps and match counts)
+            // right, but a row matched by several of them is only output once, with the first
+            let mut first_match: Option<usize> = None;
+            for (selector_idx, row_selector) in row_selectors.iter_mut().enumerate() {
+                if row_selector.matches(row_idx, row, split_rows.len()) {
+                    first_match.get_or_insert(selector_idx);
+                    matched_selectors[selector_idx] = true;
+                }
+            }
+            if let Some(selector_idx) = first_match {
+                row_idxs.push((selector_idx, row_idx));
+            }
+        }
+        if !args.quiet || args.strict {
+            for (row_selector, matched) in row_selectors.iter().zip(matched_selectors) {
+                if let Some(occurrence) = row_selector.occurrence {
+                    if row_selector.match_count < occurrence {
+                        let message = format!(
+                            "row selector '{}' asked for match {} but only matched {}",
+                            row_selector.raw, occurrence, row_selector.match_count
+                        );
+                        warn_or_fail(args.strict, message);
+                    }
+                } else if args.strict && !matched && !row_selector.raw.is_empty() {
+                    let message = format!("row selector '{}' matched no rows", row_selector.raw);
+                    exit_with_ock_error(error::OckError::Strict(message));
+                }
+            }
+        }
+        if args.preserve_order {
+            // Stable, so rows matched by the same selector stay in input order
+            row_idxs.sort_by_key(|&(selector_idx, _)| selector_idx);
+        }
+        row_idxs.into_iter().map(|(_, row_idx)| row_idx).collect()
+    };
+    report_timing(args.timing, "selecting rows", started);
+    // Add the rows around each selected row, like grep's -A, -B, and -C. Overlapping context is
+    // merged, so no row is output twice
+    let rows_before = args.before.unwrap_or(args.context);
+    let rows_after = args.after.unwrap_or(args.context);
+    if rows_before > 0 || rows_after > 0 {
+        let mut context_rows: BTreeSet<usize> = BTreeSet::new();
+        for &row_idx in &selected_rows {
+            let first_row_idx = row_idx.saturating_sub(rows_before);
+            let last_row_idx = (row_idx + rows_after).min(split_rows.len() - 1);
+            context_rows.extend(first_row_idx..=last_row_idx);
+        }
+        selected_rows = context_rows.into_iter().collect();
+    }
+    let output_format = if args.tsv {
+        cli::OutputFormat::Tsv
+    } else if args.vertical {
+        cli::OutputFormat::Records
+    } else {
+        args.output_format
+    };
+    if args.with_header_meta && output_format != cli::OutputFormat::Json {
+        let message = "--with-header-meta only applies to --output-format json";
+        exit_with_ock_error(error::OckError::Format(message.to_string()));
+    }
+    let structured_output = output_format.is_structured();
+    // Where the header ended up in the output, if it was printed as a row, so sorting can leave it
+    let mut header_output_idx: Option<usize> = None;
+    // The header is only kept out of the tail and totals when it was used to pick columns.
+    // Otherwise it's a row like any other, e.g. the first number from `seq`
+    let header_picked_columns = columns_given && !rows_given;
+    // With --tail, only the last rows are kept as they're output, unless sorting or dropping rows
+    // needs them all first
+    let streaming_tail = args.tail.filter(|_| {
+        let needs_all_rows = args.squeeze_blank_rows || args.unique;
+        args.sort.is_none() && args.sort_numeric.is_none() && !needs_all_rows
+    });
+    let mut tail_rows: VecDeque<Vec<String>> = VecDeque::new();
+    // Columns matched against each table's own header with --group-by-blank, worked out the first
+    // time a row from that table is output
+    let mut table_cols: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut last_table_idx: Option<usize> = None;
+    for row_idx in selected_rows {
+        let row = &split_rows[row_idx];
+        let is_header = (has_header && row_idx == header_idx)
+            || table_headers.binary_search(&row_idx).is_ok();
+        if structured_output && is_header {
+            // The header provides keys for structured output, rather than being a row of its own
+            continue;
+        }
+        if !is_header && !conditions.is_empty() {
+            let cells = column_splitter.split(row);
+            let meets_conditions = conditions.iter().all(|(col_idx, condition)| {
+                condition.matches(cells.get(*col_idx).map_or("", |cell| cell.as_str()))
+            });
+            if !meets_conditions {
+                continue;
+            }
+        }
+        if has_header && row_idx == header_idx && cut_field_ranges.is_none() {
+            header_output_idx = Some(output.len());
+        }
+        let cells = if let Some(field_ranges) = &cut_field_ranges {
+            vec![cut_fields(row, field_ranges, &cut_delimiter, cut_joiner)]
+        } else if args.per_row_headers {
+            // Match column selectors against this row's own cells rather than the header's, using
+            // a fresh copy of the selectors as matching updates their state
+            let (row_cols, _) = get_columns(
+                row,
+                &mut column_selectors.clone(),
+                &column_splitter,
+                args.invert_columns,
+                None,
+            );
+            if row_cols.is_empty() {
+                continue;
+            }
+            get_cells(row, &row_cols, &column_splitter, &cell_options)
+        } else if args.per_row_indices && !is_header {
+            // Match selectors against the header again, but with negative indices counting back
+            // from the end of this row, using the selectors as they were before the header
+            // resolved them
+            let (row_cols, _) = get_columns(
+                header_row.map_or("", |row| row.as_str()),
+                &mut unresolved_column_selectors.clone(),
+                &column_splitter,
+                args.invert_columns,
+                Some(column_splitter.split(row).len()),
+            );
+            get_cells(row, &row_cols, &column_splitter, &cell_options)
+        } else if args.group_by_blank {
+            let table_idx = row_tables[row_idx];
+            if output_format == cli::OutputFormat::Text
+                && last_table_idx.is_some_and(|last_table_idx| last_table_idx != table_idx)
+            {
+                // Keep a blank row between tables, as in the input
+                output.push(Vec::new());
+            }
+            last_table_idx = Some(table_idx);
+            let row_cols = table_cols.entry(table_idx).or_insert_with(|| {
+                let (row_cols, _) = get_columns(
+                    &split_rows[table_headers[table_idx]],
+                    &mut unresolved_column_selectors.clone(),
+                    &column_splitter,
+                    args.invert_columns,
+                    None,
+                );
+                row_cols
+            });
+            let cells = get_cells(row, row_cols, &column_splitter, &cell_options);
+            if structured_output {
+                // Structured output is keyed by the first table's header, so line cells up with
+                // its names in case this table's columns come in a different order
+                let table_header = &split_rows[table_headers[table_idx]];
+                let names = get_cells(table_header, row_cols, &column_splitter, &cell_options);
+                header_cells
+                    .iter()
+                    .map(|name| {
+                        let cell_idx = names.iter().position(|other| other == name);
+                        cell_idx.and_then(|idx| cells.get(idx).cloned()).unwrap_or_default()
+                    })
+                    .collect()
+            } else {
+                cells
+            }
+        } else {
+            get_cells(row, &export_cols, &column_splitter, &cell_options)
+        };
+        match streaming_tail {
+            Some(tail) if !(is_header && header_picked_columns) => {
+                // Ring buffer of the last rows, so memory stays bounded by --tail
+                if tail_rows.len() == tail {
+                    tail_rows.pop_front();
+                }
+                tail_rows.push_back(cells);
+            }
+            _ => output.push(cells),
+        }
+    }
+    output.extend(tail_rows);
+
+    // Sort rows after the header by one of the output columns. Sorting is stable, so ties keep
+    // their input order
+    if let Some(sort_column) = args.sort.or(args.sort_numeric) {
+        let data_start = header_output_idx.map_or(0, |idx| idx + 1).min(output.len());
+        let sort_cell = |row: &[String]| row.get(sort_column - 1).cloned().unwrap_or_default();
+        if args.sort_numeric.is_some() {
+            output[data_start..].sort_by(|a, b| compare_numeric(&sort_cell(a), &sort_cell(b)));
+        } else {
+            output[data_start..].sort_by_key(|row| sort_cell(row));
+        }
+    }
+
+    // Drop rows where none of the selected cells have anything in them
+    if args.squeeze_blank_rows {
+        output.retain(|row| row.iter().any(|cell| !cell.is_empty()));
+    }
+
+    // Drop rows already seen, like `sort -u` but without sorting
+    if args.unique {
+        let mut seen: HashSet<Vec<String>> = HashSet::new();
+        output.retain(|row| seen.insert(row.clone()));
+    }
+
+    // Keep only the last rows after the header, if they weren't already cut down while selecting
+    if let Some(tail) = args.tail {
+        let data_start = header_output_idx
+            .filter(|_| header_picked_columns)
+            .map_or(0, |idx| idx + 1)
+            .min(output.len());
+        let excess = (output.len() - data_start).saturating_sub(tail);
+        output.drain(data_start..data_start + excess);
+    }
+
+    // Swap rows and columns
+    if args.transpose {
+        output = output::transpose(&output);
+    }
+
+    // Like grep, exit with 1 if nothing was selected
+    if output.is_empty() {
+        if output_format == cli::OutputFormat::Html && !args.quiet {
+            // Still give a table, with just the header if there is one, so there's something to
+            // embed
+            println!("{}", output::to_html(&header_cells, &[]).join("\n"));
+        } else if output_format == cli::OutputFormat::Yaml && !args.quiet {
+            println!("{}", output::to_yaml(&header_cells, &[]).join("\n"));
+        } else if output_format == cli::OutputFormat::Json && !args.quiet {
+            // Still give valid JSON, so whatever reads it doesn't choke on nothing
+            let lines = if args.with_header_meta {
+                output::to_json_with_header_meta(&header_cells, &[])
+            } else {
+                output::to_json(&header_cells, &[])
+            };
+            println!("{}", lines.join("\n"));
+        }
+        std::process::exit(1);
+    }
+    if args.quiet {
+        return;
+    }
+
+    // Print totals of a column rather than the rows themselves, like awk '{s+=$2} END{print s}'
+    if args.sum.is_some() || args.mean.is_some() {
+        let data_rows = output
+            .iter()
+            .enumerate()
+            .filter(|(row_idx, _)| !header_picked_columns || Some(*row_idx) != header_output_idx)
+            .map(|(_, row)| row);
+        if let Some(column) = args.sum {
+            let numbers = column_numbers(data_rows.clone(), column);
+            println!("{}", numbers.iter().sum::<f64>());
+        }
+        if let Some(column) = args.mean {
+            let numbers = column_numbers(data_rows, column);
+            if numbers.is_empty() {
+                eprintln!("warning: no numbers in column {} to take the mean of", column);
+                std::process::exit(1);
             }
+            println!("{}", numbers.iter().sum::<f64>() / numbers.len() as f64);
         }
+        return;
+    }
+
+    // Print how often each value of a column comes up rather than the rows themselves, most
+    // common first and then alphabetically
+    if let Some(column) = args.count_unique {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for (row_idx, row) in output.iter().enumerate() {
+            // The first row is only a header, not a value, when column selectors used it
+            if header_picked_columns && Some(row_idx) == header_output_idx {
+                continue;
+            }
+            if let Some(cell) = row.get(column - 1) {
+                *counts.entry(cell.clone()).or_insert(0) += 1;
+            }
+        }
+        let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+        counts.sort_by(|(value, count), (other_value, other_count)| {
+            other_count.cmp(count).then_with(|| value.cmp(other_value))
+        });
+        let rows: Vec<Vec<String>> =
+            counts.into_iter().map(|(value, count)| vec![count.to_string(), value]).collect();
+        if rows.is_empty() {
+            eprintln!("warning: no values in column {} to count", column);
+            std::process::exit(1);
+        }
+        println!("{}", format_columns(&rows, &AlignOptions::default()).join("\n"));
+        return;
+    }
+
+    // Reprint the header every N rows so it stays in view when paging through long output
+    if args.header_repeat > 0
+        && columns_given
+        && !structured_output
+        && !args.transpose
+        && !header_cells.is_empty()
+    {
+        // Count only data rows, so the header's own row doesn't shorten the first block
+        let mut repeated_output: Vec<Vec<String>> = Vec::new();
+        let mut data_rows: usize = 0;
+        for (row_idx, row) in output.into_iter().enumerate() {
+            if Some(row_idx) != header_output_idx {
+                if data_rows > 0 && data_rows.is_multiple_of(args.header_repeat) {
+                    repeated_output.push(header_cells.clone());
+                }
+                data_rows += 1;
+            }
+            repeated_output.push(row);
+        }
+        output = repeated_output;
     }
 
     // Print results to screen
-    for row in &output {
-        let mut formatted_row: String = String::new();
-        for (idx, cell) in row.iter().enumerate() {
-            let formatted_cell = format!("{:width$}", cell, width = max_column_lengths[idx] + 2);
-            formatted_row.push_str(&formatted_cell);
+    let output_row_delimiter = if args.paragraph && args.output_row_delimiter == r"\n" {
+        // Keep paragraphs apart with a blank line, as they were in the input
+        String::from("\n\n")
+    } else {
+        utils::unescape(&args.output_row_delimiter)
+    };
+    let align_options = AlignOptions {
+        squeeze: args.squeeze,
+        min_width: args.min_col_width,
+        pad_char: args.pad_char,
+        escape: args.escape_output,
+        ascii_width: args.ascii_width,
+        group_digits: args.group_digits,
+    };
+    let mut formatted_rows = match output_format {
+        cli::OutputFormat::Text => format_columns(&output, &align_options),
+        cli::OutputFormat::Tsv => output::to_tsv(&output),
+        cli::OutputFormat::Toml => output::to_toml(&header_cells, &output),
+        cli::OutputFormat::Html => output::to_html(&header_cells, &output),
+        cli::OutputFormat::Json if args.with_header_meta => {
+            output::to_json_with_header_meta(&header_cells, &output)
         }
-        println!("{}", formatted_row)
+        cli::OutputFormat::Json => output::to_json(&header_cells, &output),
+        cli::OutputFormat::Yaml => output::to_yaml(&header_cells, &output),
+        cli::OutputFormat::Records => output::to_records(&header_cells, &output),
+    };
+    // Only print what fits on the terminal, leaving the last line to say how much was left out
+    let mut hidden_rows = 0;
+    if args.page && io::stdout().is_terminal() {
+        let height = terminal_height();
+        if formatted_rows.len() > height {
+            let shown_rows = height.saturating_sub(1);
+            hidden_rows = formatted_rows.len() - shown_rows;
+            formatted_rows.truncate(shown_rows);
+        }
+    }
+    for (row_idx, formatted_row) in formatted_rows.iter().enumerate() {
+        let is_last_row = hidden_rows == 0 && row_idx == formatted_rows.len() - 1;
+        if args.no_trailing_newline && is_last_row {
+            // Leave off the delimiter after the last row if asked to
+            print!("{}", formatted_row)
+        } else if args.paragraph && is_last_row && output_row_delimiter == "\n\n" {
+            // Only paragraphs need a blank line between them, so end the last with a single newline
+            println!("{}", formatted_row)
+        } else {
+            print!("{}{}", formatted_row, output_row_delimiter)
+        }
+    }
+    if hidden_rows > 0 {
+        println!("... ({} more rows)", hidden_rows);
     }
 }
@@ -0,0 +1,350 @@
+/// Key for each cell in a row, taken from the header. Cells past the end of the header are keyed
+/// by their (1-based) position instead
+fn cell_keys(header: &[String], row_len: usize) -> Vec<String> {
+    (0..row_len)
+        .map(|idx| match header.get(idx) {
+            Some(key) => key.clone(),
+            None => format!("column_{}", idx + 1),
+        })
+        .collect()
+}
+
+/// Quote text as a TOML basic string, escaping quotes, backslashes, and control characters
+fn toml_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// TOML key, left bare if it's made up of only letters, digits, dashes, and underscores
+fn toml_key(key: &str) -> String {
+    let is_bare = !key.is_empty()
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_bare {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+/// Format rows as TOML, keyed by the header's names, returning one String per line
+/// A single row becomes `key = "value"` pairs, while several become an array of tables named
+/// `rows`. Values are always quoted strings, so there's no guessing at types.
+pub fn to_toml(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for (row_idx, row) in rows.iter().enumerate() {
+        if rows.len() > 1 {
+            if row_idx > 0 {
+                lines.push(String::new());
+            }
+            lines.push(String::from("[[rows]]"));
+        }
+        for (key, value) in cell_keys(header, row.len()).iter().zip(row) {
+            lines.push(format!("{} = {}", toml_key(key), toml_string(value)));
+        }
+    }
+    lines
+}
+
+/// Escape a cell for TSV, so tabs and newlines in it can't be mistaken for separators
+fn tsv_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Escape tabs, carriage returns, newlines, and other control characters (as `\xNN`) so hidden
+/// characters in a cell show up when it's printed, like `cat -v`
+pub fn escape_nonprintable(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format rows as tab-separated values, returning one String per row
+pub fn to_tsv(rows: &[Vec<String>]) -> Vec<String> {
+    rows.iter()
+        .map(|row| row.iter().map(|cell| tsv_escape(cell)).collect::<Vec<String>>().join("\t"))
+        .collect()
+}
+
+/// Escape text for HTML, so it shows as written rather than being read as markup
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format rows as an HTML table, with the header's names as header cells, returning one String per
+/// line. With no header or rows, this is just an empty table.
+pub fn to_html(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    if header.is_empty() && rows.is_empty() {
+        return vec![String::from("<table></table>")];
+    }
+    let html_row = |row: &[String], tag: &str| {
+        let cells: String = row
+            .iter()
+            .map(|cell| format!("<{}>{}</{}>", tag, html_escape(cell), tag))
+            .collect();
+        format!("  <tr>{}</tr>", cells)
+    };
+    let mut lines: Vec<String> = vec![String::from("<table>")];
+    if !header.is_empty() {
+        lines.push(html_row(header, "th"));
+    }
+    lines.extend(rows.iter().map(|row| html_row(row, "td")));
+    lines.push(String::from("</table>"));
+    lines
+}
+
+/// Quote text as a JSON string, escaping quotes, backslashes, and control characters
+pub fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// JSON array of strings, on one line
+fn json_array(cells: &[String]) -> String {
+    let cells: Vec<String> = cells.iter().map(|cell| json_string(cell)).collect();
+    format!("[{}]", cells.join(", "))
+}
+
+/// Lines of a JSON array, one item per line, with commas between them
+fn json_array_lines(items: Vec<String>, indent: &str) -> Vec<String> {
+    let item_count = items.len();
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(idx, item)| {
+            let comma = if idx + 1 < item_count { "," } else { "" };
+            format!("{}{}{}", indent, item, comma)
+        })
+        .collect()
+}
+
+/// Format rows as a JSON array of objects keyed by the header's names, returning one String per
+/// line. Values are always strings, so there's no guessing at types. No rows gives `[]`.
+pub fn to_json(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    if rows.is_empty() {
+        return vec![String::from("[]")];
+    }
+    let objects: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let pairs: Vec<String> = cell_keys(header, row.len())
+                .iter()
+                .zip(row)
+                .map(|(key, value)| format!("{}: {}", json_string(key), json_string(value)))
+                .collect();
+            format!("{{{}}}", pairs.join(", "))
+        })
+        .collect();
+    let mut lines = vec![String::from("[")];
+    lines.extend(json_array_lines(objects, "  "));
+    lines.push(String::from("]"));
+    lines
+}
+
+/// Format rows as a JSON object holding the header's names as `columns`, and the rows as arrays
+/// under `rows`, returning one String per line
+pub fn to_json_with_header_meta(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let mut lines = vec![String::from("{")];
+    lines.push(format!("  \"columns\": {},", json_array(header)));
+    if rows.is_empty() {
+        lines.push(String::from("  \"rows\": []"));
+        lines.push(String::from("}"));
+        return lines;
+    }
+    lines.push(String::from("  \"rows\": ["));
+    lines.extend(json_array_lines(rows.iter().map(|row| json_array(row)).collect(), "    "));
+    lines.push(String::from("  ]"));
+    lines.push(String::from("}"));
+    lines
+}
+
+/// YAML scalar for text, left plain unless YAML would read it as something else (a number, a
+/// boolean, null, or structure), in which case it's double-quoted the same way as JSON
+fn yaml_string(text: &str) -> String {
+    // YAML 1.1 and 1.2 between them read all of these as booleans, null, infinity, or not-a-number
+    let reserved = [
+        "true", "false", "yes", "no", "y", "n", "on", "off", "null", "~", ".inf", "+.inf", "-.inf",
+        ".nan",
+    ];
+    let needs_quotes = text.is_empty()
+        || text.contains([':', '#'])
+        || text.starts_with(['-', '?', '[', ']', '{', '}', ',', '&', '*', '!', '|', '>', '\'', '"'])
+        || text.starts_with(['%', '@', '`'])
+        || text.starts_with(char::is_whitespace)
+        || text.ends_with(char::is_whitespace)
+        || text.chars().any(char::is_control)
+        || is_yaml_number(text)
+        || reserved.contains(&text.to_lowercase().as_str());
+    if needs_quotes {
+        json_string(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Whether YAML would read text as a number, including YAML 1.1's underscored digits (`1_000`)
+/// and hex, octal, and binary integers (`0x1f`, `0o17`, `0b11`)
+fn is_yaml_number(text: &str) -> bool {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text).replace('_', "");
+    let radix_digits = [("0x", 16), ("0o", 8), ("0b", 2)].iter().find_map(|(prefix, radix)| {
+        digits.to_lowercase().strip_prefix(prefix).map(|rest| (rest.to_string(), *radix))
+    });
+    if let Some((rest, radix)) = radix_digits {
+        return !rest.is_empty() && rest.chars().all(|c| c.is_digit(radix));
+    }
+    !digits.is_empty() && digits.parse::<f64>().is_ok()
+}
+
+/// Format rows as YAML, returning one String per line. Rows are maps keyed by the header's names,
+/// or lists of cells if there's no header, and no rows at all is an empty list.
+pub fn to_yaml(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    if rows.is_empty() {
+        return vec![String::from("[]")];
+    }
+    let mut lines: Vec<String> = Vec::new();
+    for row in rows {
+        let items: Vec<String> = if header.is_empty() {
+            row.iter().map(|cell| format!("- {}", yaml_string(cell))).collect()
+        } else {
+            cell_keys(header, row.len())
+                .iter()
+                .zip(row)
+                .map(|(key, value)| format!("{}: {}", yaml_string(key), yaml_string(value)))
+                .collect()
+        };
+        if items.is_empty() {
+            lines.push(String::from(if header.is_empty() { "- []" } else { "- {}" }));
+        }
+        // Each row is an item of the top level list, with its cells indented beneath the dash
+        for (item_idx, item) in items.iter().enumerate() {
+            let prefix = if item_idx == 0 { "- " } else { "  " };
+            lines.push(format!("{}{}", prefix, item));
+        }
+    }
+    lines
+}
+
+/// Format rows as blocks of `key: value` lines, one line per cell keyed by the header's names, with
+/// keys padded to line up and a blank line between rows, returning one String per line
+pub fn to_records(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for (row_idx, row) in rows.iter().enumerate() {
+        if row_idx > 0 {
+            lines.push(String::new());
+        }
+        let keys = cell_keys(header, row.len());
+        let key_width = keys.iter().map(|key| crate::utils::display_width(key)).max().unwrap_or(0);
+        for (key, value) in keys.iter().zip(row) {
+            let padding = " ".repeat(key_width - crate::utils::display_width(key));
+            lines.push(format!("{}:{} {}", key, padding, value));
+        }
+    }
+    lines
+}
+
+/// Swap rows and columns, padding ragged rows with empty cells so every column is complete
+pub fn transpose(rows: &[Vec<String>]) -> Vec<Vec<String>> {
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    (0..width)
+        .map(|col_idx| {
+            rows.iter().map(|row| row.get(col_idx).cloned().unwrap_or_default()).collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rows of owned cells from rows of string slices
+    fn table(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|cell| cell.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn json_with_no_rows_is_an_empty_array() {
+        let header = table(&[&["pid", "cmd"]]).remove(0);
+        assert_eq!(to_json(&header, &[]), vec!["[]"]);
+        let expected = ["{", "  \"columns\": [\"pid\", \"cmd\"],", "  \"rows\": []", "}"];
+        assert_eq!(to_json_with_header_meta(&header, &[]), expected);
+    }
+
+    #[test]
+    fn yaml_quotes_text_read_as_something_else() {
+        for text in ["0x1F", "0o17", "0b101", "-0x1f", "1_000", "+1_000.5", "3.5", "-7", "1e3"] {
+            assert_eq!(yaml_string(text), format!("\"{}\"", text), "{}", text);
+        }
+        for text in [".inf", "-.Inf", "+.INF", ".NaN", "y", "N", "Yes", "off", "~", "null", ""] {
+            assert_eq!(yaml_string(text), format!("\"{}\"", text), "{}", text);
+        }
+        for text in ["0x", "0xZZ", "0o9", "_", "a_b", "yo", "x_1", "hello world", "v1.2"] {
+            assert_eq!(yaml_string(text), text, "{}", text);
+        }
+    }
+
+    #[test]
+    fn transpose_swaps_rows_and_columns() {
+        let rows = table(&[&["pid", "cmd"], &["1", "init"], &["2", "bash"]]);
+        let expected = table(&[&["pid", "1", "2"], &["cmd", "init", "bash"]]);
+        assert_eq!(transpose(&rows), expected);
+        assert_eq!(transpose(&expected), rows);
+    }
+
+    #[test]
+    fn transpose_pads_ragged_rows() {
+        let rows = table(&[&["a", "b"], &["c"]]);
+        assert_eq!(transpose(&rows), table(&[&["a", "c"], &["b", ""]]));
+    }
+}
@@ -1,49 +1,203 @@
+use crate::error::OckError;
 use regex::Regex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 include!("utils.rs");
 
-/// Keep track of user column and row selections
+thread_local! {
+    /// Compiled regexes keyed by pattern, so the same pattern is only compiled once
+    /// Each thread has its own cache, so nothing has to be locked and threads never see each
+    /// other's regexes. Callers get their own clone of each regex, so clearing the cache mid-run
+    /// doesn't affect ones already handed out, it only means the next request for a pattern
+    /// compiles it again.
+    static REGEX_CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+/// Get the compiled regex for a pattern from the cache, compiling and caching it if it's new
+pub fn get_or_compile_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    REGEX_CACHE.with_borrow_mut(|cache| {
+        if let Some(regex) = cache.get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern)?;
+        cache.insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    })
+}
+
+/// Empty this thread's regex cache, e.g. to bound memory when embedding ock in a long-running
+/// program
+#[allow(dead_code)]
+pub fn clear_regex_cache() {
+    REGEX_CACHE.with_borrow_mut(|cache| cache.clear());
+}
+
+/// Number of compiled regexes in this thread's cache
+#[allow(dead_code)]
+pub fn regex_cache_len() -> usize {
+    REGEX_CACHE.with_borrow(|cache| cache.len())
+}
+
+/// Errors that can occur while parsing row or column selectors
+/// Positions are 1-based: `position` is the selector's place in the comma-separated list, and
+/// `component` is the colon-separated component within it that failed
 #[derive(Debug)]
+pub enum SelectorError {
+    /// Selector is malformed, e.g. has too many components
+    InvalidSelector { selector: String, position: usize, component: usize, reason: String },
+
+    /// Selector component could not be compiled as a regex
+    InvalidRegex { selector: String, position: usize, component: usize, source: regex::Error },
+
+    /// Selector read from a file is invalid, noting the line (1-based) it came from
+    InFile { path: String, line: usize, error: Box<SelectorError> },
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SelectorError::InvalidSelector { selector, position, component, reason } => {
+                write!(
+                    f,
+                    "selector #{} ('{}') component {}: {}",
+                    position, selector, component, reason
+                )
+            }
+            SelectorError::InvalidRegex { selector, position, component, source } => {
+                write!(
+                    f,
+                    "selector #{} ('{}') component {}: invalid regex: {}",
+                    position, selector, component, source
+                )
+            }
+            SelectorError::InFile { path, line, error } => {
+                write!(f, "{} (line {} of {})", error, line, path)
+            }
+        }
+    }
+}
+
+impl SelectorError {
+    /// Shift the selector position along, for when selectors parsed separately are numbered as one
+    /// list (e.g. from several `-c` flags)
+    pub fn offset_position(mut self, offset: usize) -> SelectorError {
+        match &mut self {
+            SelectorError::InvalidSelector { position, .. }
+            | SelectorError::InvalidRegex { position, .. } => *position += offset,
+            SelectorError::InFile { .. } => {}
+        }
+        self
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+/// Keep track of user column and row selections
+#[derive(Clone, Debug)]
 pub struct Selector {
-    /// Index of first row to grab (start of range)
-    pub start_idx: usize,
+    /// Selector as it was written, used when reporting on it
+    pub raw: String,
+
+    /// Index of first row to grab (start of range), negative counts back from the end
+    pub start_idx: i64,
+
+    /// Regex of first to grab (start of range), if the start isn't an index
+    pub start_regex: Option<regex::Regex>,
+
+    /// Offset from the item matching start_regex, when the start is relative to an anchor
+    pub start_offset: Option<i64>,
 
-    /// Regex of first to grab (start of range)
-    pub start_regex: regex::Regex,
+    /// Index of last row to grab (end of range), negative counts back from the end
+    pub end_idx: i64,
 
-    /// Index of last row to grab (end of range)
-    pub end_idx: usize,
+    /// Regex of last row to grab (end of range), if the end isn't an index
+    pub end_regex: Option<regex::Regex>,
 
-    /// Regex of last row to grab (end of range)
-    pub end_regex: regex::Regex,
+    /// Offset from the item matching end_regex, when the end is relative to an anchor
+    pub end_offset: Option<i64>,
 
     /// Step size between start and end of range
     pub step: usize,
 
+    /// Leave out the item matching the end regex, ending the range just before it
+    pub exclusive_end: bool,
+
+    /// Start the range again each time the start regex matches after it has ended
+    pub repeat_ranges: bool,
+
+    /// Which match of a lone regex to select (1-based), rather than all of them
+    pub occurrence: Option<usize>,
+
+    /// Modulus and (0-based) remainder an item's index has to have, e.g. from `%3==1`, instead of
+    /// being in a range
+    pub modulo: Option<(usize, usize)>,
+
+    /// Regex an item also has to match to be selected, from after an `&` (e.g. `2:8&cpu`)
+    pub filter: Option<regex::Regex>,
+
+    /// Number of items a lone regex has matched so far
+    pub match_count: usize,
+
     /// Keep track of when to stop adding rows from range to output
     pub stopped: bool,
+
+    /// Whether negative indices have been resolved against a length yet
+    pub resolved: bool,
 }
 
 impl Default for Selector {
     /// Defaults to implement a new selector without defining each field individually
     fn default() -> Selector {
         Selector {
-            /// Default start to 0, the first row/column
+            // Default raw selector to empty, which selects everything
+            raw: String::new(),
+
+            // Default start to 0, the first row/column
             start_idx: 0,
 
-            /// Default start to ".^", an impossible Regex that nothing will match
-            start_regex: Regex::new(r".^").unwrap(),
+            // Default to no start regex, so the start index is used
+            start_regex: None,
+
+            // Default to no start anchor
+            start_offset: None,
 
-            /// Default end to the max usize value (i.e. 2^64 - 1 on an amd64 machine)
-            end_idx: std::usize::MAX,
+            // Default end to the max i64 value (i.e. 2^63 - 1)
+            end_idx: i64::MAX,
 
-            /// Default end to ".^", an impossible Regex that nothing will match
-            end_regex: Regex::new(r".^").unwrap(),
+            // Default to no end regex, so the end index is used
+            end_regex: None,
 
-            /// Default step to 1 to get each row
+            // Default to no end anchor
+            end_offset: None,
+
+            // Default step to 1 to get each row
             step: 1,
 
-            /// Default stopped to false so we output rows unless otherwise specified
+            // Default to including the item matching the end regex in the range
+            exclusive_end: false,
+
+            // Default to selecting only the first block a range matches
+            repeat_ranges: false,
+
+            // Default to selecting every match of a lone regex
+            occurrence: None,
+
+            // Default to selecting by range rather than by index modulo
+            modulo: None,
+
+            // Default to no filter, so everything in the range is selected
+            filter: None,
+
+            // Default match count to 0, as nothing has been matched yet
+            match_count: 0,
+
+            // Default stopped to false so we output rows unless otherwise specified
             stopped: false,
+
+            // Default resolved to false, as the length being selected from isn't known yet
+            resolved: false,
         }
     }
 }
@@ -56,22 +210,435 @@ impl PartialEq for Selector {
     fn eq(&self, other: &Self) -> bool {
         self.start_idx == other.start_idx
             && utils::regex_eq(&self.start_regex, &other.start_regex)
+            && self.start_offset == other.start_offset
             && self.end_idx == other.end_idx
             && utils::regex_eq(&self.end_regex, &other.end_regex)
+            && self.end_offset == other.end_offset
             && self.step == other.step
+            && self.exclusive_end == other.exclusive_end
+            && self.repeat_ranges == other.repeat_ranges
+            && self.occurrence == other.occurrence
+            && self.modulo == other.modulo
+            && utils::regex_eq(&self.filter, &other.filter)
             && self.stopped == other.stopped
     }
 }
 
+impl Selector {
+    /// Resolve negative indices, which count back from the end, against the length of the rows or
+    /// columns being selected from. A lone out of bounds negative index matches nothing, while an
+    /// out of bounds range start is clamped to the first item, like Python's list slicing.
+    /// Unlike Python, a negative end is inclusive like any other, so `-3:-1` is the last three
+    /// items, the same as `-3:`.
+    /// Only the first call has any effect, so resolving again (even against another length) does
+    /// nothing.
+    pub fn resolve_indices(&mut self, len: usize) {
+        if self.resolved {
+            return;
+        }
+        self.resolved = true;
+        let len = len as i64;
+        let single_index = self.start_idx == self.end_idx;
+        let negative_start = self.start_idx < 0;
+        if negative_start {
+            self.start_idx += len;
+            if !single_index {
+                self.start_idx = self.start_idx.max(0);
+            }
+        }
+        if self.end_idx < 0 {
+            self.end_idx += len;
+            // A range ending before the first item selects nothing, as in Python
+            if !single_index && self.end_idx < 0 {
+                self.stopped = true;
+            }
+        }
+        if negative_start && !single_index && self.end_idx < self.start_idx {
+            // Likewise for a range ending before its clamped start
+            self.stopped = true;
+        }
+    }
+
+    /// Turn bounds relative to an anchor (e.g. `@command+1`) into indices, using the first of the
+    /// items each anchor regex matches. If an anchor matches nothing, neither does the selector.
+    pub fn resolve_anchors(&mut self, items: &[String]) {
+        let single_index = self.start_offset.is_some()
+            && self.start_offset == self.end_offset
+            && utils::regex_eq(&self.start_regex, &self.end_regex);
+        if let Some(offset) = self.start_offset.take() {
+            match items.iter().position(|item| utils::regex_matches(&self.start_regex, item)) {
+                Some(anchor_idx) => {
+                    self.start_idx = anchor_idx as i64 + offset;
+                    if !single_index {
+                        self.start_idx = self.start_idx.max(0);
+                    }
+                    self.start_regex = None;
+                }
+                None => self.stopped = true,
+            }
+        }
+        if let Some(offset) = self.end_offset.take() {
+            match items.iter().position(|item| utils::regex_matches(&self.end_regex, item)) {
+                Some(anchor_idx) => {
+                    self.end_idx = anchor_idx as i64 + offset;
+                    self.end_regex = None;
+                }
+                None => self.stopped = true,
+            }
+        }
+    }
+
+    /// Check whether the item at `item_idx` (0-based) of `len` items belongs to this selection
+    /// Items must be checked in order, as ranges and steps are tracked in the selector as it goes,
+    /// e.g. a regex range starts at the first item its start regex matches. Negative indices are
+    /// resolved against `len` on the first call.
+    pub fn matches(&mut self, item_idx: usize, item: &str, len: usize) -> bool {
+        // The range is always checked, so it keeps track of where it is even for items the filter
+        // leaves out
+        let in_range = self.matches_range(item_idx, item, len);
+        in_range && (self.filter.is_none() || utils::regex_matches(&self.filter, item))
+    }
+
+    /// Check whether the item at `item_idx` is in the selector's range (or other selection), before
+    /// any `&` filter is applied
+    fn matches_range(&mut self, item_idx: usize, item: &str, len: usize) -> bool {
+        self.resolve_indices(len);
+        if self.stopped {
+            // Range has already ended
+            return false;
+        }
+        if let Some((modulus, remainder)) = self.modulo {
+            return item_idx % modulus == remainder;
+        }
+        let item_idx = item_idx as i64;
+        let mut in_sequence = false;
+        if item_idx != self.start_idx
+            && self.start_idx == self.end_idx
+            && utils::regex_eq(&self.start_regex, &self.end_regex)
+            && self.start_regex.is_some()
+        {
+            // If a regex is provided as the only selector, just check against it, counting matches
+            // if only one of them is wanted
+            if !utils::regex_matches(&self.start_regex, item) {
+                return false;
+            }
+            self.match_count += 1;
+            return self.occurrence.is_none_or(|occurrence| self.match_count == occurrence);
+        }
+        if (item_idx == self.start_idx && self.start_regex.is_none())
+            || (self.start_idx == i64::MAX && utils::regex_matches(&self.start_regex, item))
+        {
+            // Sequence started, where a start regex only counts until its first match, so a later
+            // match doesn't restart the range (or throw its step off)
+            in_sequence = true;
+            self.start_idx = item_idx;
+            if (utils::regex_eq(&self.end_regex, &self.start_regex)
+                && self.start_regex.is_some())
+                || (self.end_idx == self.start_idx)
+            {
+                // Only one column selected
+                self.stopped = true;
+            }
+        } else if item_idx == self.end_idx
+            && self.start_idx == i64::MAX
+            && self.start_regex.is_some()
+        {
+            // A numeric end reached before the start regex has matched, so the range can't start
+            // until after it has ended, and selects nothing
+            self.stopped = true;
+        } else if item_idx == self.end_idx
+            || (item_idx > self.start_idx && utils::regex_matches(&self.end_regex, item))
+        {
+            // Sequence end, which is only included if it falls on the step, and isn't an end regex
+            // match being left out. An end regex matching before the range has started is ignored
+            let excluded = self.exclusive_end && item_idx != self.end_idx;
+            in_sequence = !excluded
+                && (item_idx < self.start_idx
+                    || ((item_idx - self.start_idx) as usize).is_multiple_of(self.step));
+            if !self.repeat_ranges {
+                self.end_idx = item_idx;
+                self.stopped = item_idx > self.start_idx;
+            } else if item_idx > self.start_idx {
+                // Wait for the start regex to match again, rather than stopping for good
+                self.start_idx = i64::MAX;
+            }
+        } else if item_idx > self.start_idx
+            && item_idx < self.end_idx
+            && ((item_idx - self.start_idx) as usize).is_multiple_of(self.step)
+        {
+            // Sequence middle, on the step counting from wherever the range started (the index, or
+            // the item the start regex matched)
+            in_sequence = true;
+        }
+        in_sequence
+    }
+}
+
+/// Options changing how selectors are parsed
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// Treat positive indices as 0-based (0 is the first item) rather than 1-based
+    pub zero_based: bool,
+
+    /// Match regex selectors case sensitively, rather than ignoring case
+    pub case_sensitive: bool,
+
+    /// Leave the item matching a range's end regex out of the range
+    pub exclusive_end: bool,
+
+    /// Start regex ranges again whenever their start regex matches after they've ended
+    pub repeat_ranges: bool,
+
+    /// Treat selectors as globs matching the whole item, rather than regexes
+    pub glob: bool,
+
+    /// Extra regex flags (e.g. `ms` for multi-line and dot-matches-newline), merged with the `i`
+    /// used to ignore case
+    pub regex_flags: String,
+}
+
+/// Translate a glob into the equivalent regex: `*` matches anything, `?` matches any one character,
+/// and `[...]` matches one of a set of characters (`[!...]` for any character not in the set)
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::new();
+    let mut chars = glob.chars();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        match c {
+            ']' if in_class => {
+                in_class = false;
+                pattern.push(']');
+            }
+            _ if in_class => {
+                // Characters are literal in a glob class, other than `-` for ranges
+                if matches!(c, '\\' | '[' | '&' | '~' | '^') {
+                    pattern.push('\\');
+                }
+                pattern.push(c);
+            }
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                in_class = true;
+                pattern.push('[');
+                if chars.clone().next() == Some('!') {
+                    chars.next();
+                    pattern.push('^');
+                }
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    pattern.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern
+}
+
+/// Build a range from a `--from` regex (or the first item) through the first item after it that
+/// matches a `--to` regex (or the last item). Unlike `-r`, colons and commas are part of the regex.
+pub fn regex_range(
+    from: Option<&str>,
+    to: Option<&str>,
+    options: &ParseOptions,
+) -> Result<Selector, SelectorError> {
+    let mut raw: Vec<String> = Vec::new();
+    raw.extend(from.map(|from| format!("--from {}", from)));
+    raw.extend(to.map(|to| format!("--to {}", to)));
+    let raw = raw.join(" ");
+    let mut range = Selector {
+        raw: raw.clone(),
+        exclusive_end: options.exclusive_end,
+        repeat_ranges: options.repeat_ranges,
+        ..Selector::default()
+    };
+    if let Some(from) = from {
+        range.start_regex = Some(selector_regex(&raw, 0, 0, from, options)?);
+        // Set the start index to the i64 max to ensure it doesn't interfere
+        range.start_idx = i64::MAX;
+    }
+    if let Some(to) = to {
+        range.end_regex = Some(selector_regex(&raw, 0, 1, to, options)?);
+    }
+    Ok(range)
+}
+
+/// Build the regex used to match a selector component, ignoring case unless told otherwise
+fn selector_regex(
+    selector: &str,
+    position: usize,
+    component_idx: usize,
+    component: &str,
+    options: &ParseOptions,
+) -> Result<Regex, SelectorError> {
+    let flags = match (options.case_sensitive, options.regex_flags.as_str()) {
+        (true, "") => String::new(),
+        (true, extra_flags) => format!("(?{})", extra_flags),
+        (false, extra_flags) => format!("(?i{})", extra_flags),
+    };
+    let pattern = if options.glob {
+        // Globs match the whole item, rather than anywhere in it like regexes
+        format!(r"{}^{}$", flags, glob_to_regex(component))
+    } else {
+        format!(r"{}.*{}.*", flags, component)
+    };
+    get_or_compile_regex(&pattern).map_err(|e| SelectorError::InvalidRegex {
+        selector: selector.to_string(),
+        position: position + 1,
+        component: component_idx + 1,
+        source: e,
+    })
+}
+
+/// Split text on a separator, except where the separator is escaped with a backslash
+/// Escaped separators are unescaped, while any other escape (e.g. `\d`, or `\\` for a literal
+/// backslash) is left as it is for the regex to handle.
+fn split_unescaped(text: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(next) if next == separator => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    parts.last_mut().unwrap().push(c);
+                    parts.last_mut().unwrap().push(next);
+                }
+                None => parts.last_mut().unwrap().push(c),
+            }
+        } else if c == separator {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts
+}
+
+/// Split a trailing `#N` occurrence off a lone regex selector, e.g. `error#2` for the second row
+/// matching `error`. Escaping the `#` (`error\#2`) keeps it as part of the regex.
+fn split_occurrence(selector: &str) -> Option<(String, usize)> {
+    let (pattern, occurrence) = selector.rsplit_once('#')?;
+    if pattern.is_empty() || pattern.ends_with('\\') || pattern.parse::<i64>().is_ok() {
+        return None;
+    }
+    Some((pattern.to_string(), occurrence.parse::<usize>().ok()?))
+}
+
+/// Split a `N-M` range, as `cut` would take it, into its start and end, e.g. `1-3` for `1:3`. Only
+/// two plain integers count, so negative indices and regexes containing dashes are left alone.
+fn split_dash_range(selector: &str) -> Option<(String, String)> {
+    let (start, end) = selector.split_once('-')?;
+    let is_index = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    if !is_index(start) || !is_index(end) {
+        return None;
+    }
+    Some((start.to_string(), end.to_string()))
+}
+
+/// Split a `%N==R` selector into its modulus and remainder, e.g. `%3==1` for every item whose
+/// index leaves a remainder of 1 when divided by 3
+fn split_modulo(selector: &str) -> Option<(usize, usize)> {
+    let (modulus, remainder) = selector.strip_prefix('%')?.split_once("==")?;
+    let is_number = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    if !is_number(modulus) || !is_number(remainder) {
+        return None;
+    }
+    Some((modulus.parse::<usize>().ok()?, remainder.parse::<usize>().ok()?))
+}
+
+/// Split an anchor into its regex and offset, e.g. `@command+1` for the item after the one
+/// matching `command`. Only components starting with `@` and ending in `+N` or `-N` are anchors, so
+/// regexes like `utf-8` are left alone. Escaping the sign (`@a\-1`) keeps it as part of the regex.
+fn split_anchor_offset(component: &str) -> Option<(String, i64)> {
+    let component = component.strip_prefix('@')?;
+    let sign_idx = component.rfind(['+', '-'])?;
+    let (anchor, offset) = component.split_at(sign_idx);
+    if anchor.is_empty()
+        || anchor.ends_with('\\')
+        || offset.len() < 2
+        || !offset[1..].chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    Some((anchor.to_string(), offset.parse::<i64>().ok()?))
+}
+
 /// Parse either row or column selectors, turning Python-like list slicing syntax into vector of
 /// Selector structs
-pub fn parse_selectors(selectors: &String) -> Vec<Selector> {
+pub fn parse_selectors(
+    selectors: &str,
+    options: &ParseOptions,
+) -> Result<Vec<Selector>, SelectorError> {
     let mut sequences: Vec<Selector> = Vec::new();
-    // Iterate through selectors, which are separated by commas
-    for selector in selectors.split(",") {
-        let mut sequence = Selector::default();
+    // Iterate through selectors, which are separated by commas (unless escaped as `\,`)
+    for (position, selector) in split_unescaped(selectors, ',').iter().enumerate() {
+        let mut sequence = Selector {
+            raw: selector.to_string(),
+            exclusive_end: options.exclusive_end,
+            repeat_ranges: options.repeat_ranges,
+            ..Selector::default()
+        };
+        let invalid = |component_idx: usize, reason: &str| SelectorError::InvalidSelector {
+            selector: selector.to_string(),
+            position: position + 1,
+            component: component_idx + 1,
+            reason: reason.to_string(),
+        };
+        // A selector can be narrowed down with `&` and a regex that items also have to match
+        // (unless escaped as `\&`)
+        let (selector_body, filter) = match split_unescaped(selector, '&').as_slice() {
+            [body] => (body.clone(), None),
+            [body, filter] => (body.clone(), Some(filter.clone())),
+            _ => return Err(invalid(0, "only one & filter is allowed")),
+        };
         // Iterate through components in an individual selector, which are separated by colons
-        for (idx, component) in selector.split(":").enumerate() {
+        // (unless escaped as `\:`)
+        let mut components = split_unescaped(&selector_body, ':');
+        if let Some(filter) = filter {
+            if filter.is_empty() {
+                return Err(invalid(components.len(), "no regex after &"));
+            }
+            let filter_regex =
+                selector_regex(selector, position, components.len(), &filter, options)?;
+            sequence.filter = Some(filter_regex);
+        }
+        if let Some(modulo_idx) = components.iter().position(|c| split_modulo(c).is_some()) {
+            if components.len() > 1 {
+                return Err(invalid(modulo_idx, "%N==R selectors can't be part of a range"));
+            }
+            let (modulus, remainder) = split_modulo(&components[0]).unwrap();
+            if modulus == 0 {
+                return Err(invalid(0, "modulus must be positive"));
+            }
+            if remainder >= modulus {
+                return Err(invalid(0, "remainder must be less than the modulus"));
+            }
+            // Indices are 1-based unless told otherwise, so shift the remainder to match the
+            // 0-based index items are matched by
+            let remainder =
+                if options.zero_based { remainder } else { (remainder + modulus - 1) % modulus };
+            sequence.modulo = Some((modulus, remainder));
+            sequences.push(sequence);
+            continue;
+        }
+        if components.len() == 1 {
+            if let Some((start, end)) = split_dash_range(&components[0]) {
+                components = vec![start, end];
+            }
+        }
+        if components.len() == 1 {
+            if let Some((pattern, occurrence)) = split_occurrence(&components[0]) {
+                if occurrence == 0 {
+                    return Err(invalid(0, "occurrences start at 1"));
+                }
+                sequence.occurrence = Some(occurrence);
+                components[0] = pattern;
+            }
+        }
+        for (idx, component) in components.iter().enumerate() {
             // If component is empty, we do nothing
             if component.is_empty() {
                 continue
@@ -79,39 +646,66 @@ pub fn parse_selectors(selectors: &String) -> Vec<Selector> {
             // Try to parse int from component. If we're successful, use that int as a start index,
             // end index, or step. If parse() returns an error, use that component as a regex
             // pattern to match to
-            let parsed_component = component.parse::<usize>();
+            let parsed_component = component.parse::<i64>();
             match parsed_component {
-                Ok(_ok) => {
-                    // Subtract 1 from row, so 1:10 selects rows 1 to 10, not 2 to 11
-                    let number = parsed_component.as_ref().unwrap() - 1;
+                Ok(parsed) if idx == 2 => {
+                    // Steps are a count of rows rather than an index, so are used as they are
+                    if parsed < 1 {
+                        return Err(invalid(idx, "step size must be positive"));
+                    }
+                    sequence.step = parsed as usize;
+                }
+                Ok(parsed) => {
+                    // Subtract 1 from row, so 1:10 selects rows 1 to 10, not 2 to 11, unless
+                    // indices are already 0-based. Negative numbers count back from the end (-1
+                    // is the last), so are left as they are
+                    let number = match parsed {
+                        n if n < 0 || options.zero_based => n,
+                        0 => return Err(invalid(idx, "indices start at 1, see --zero-based")),
+                        n => n - 1,
+                    };
                     match idx {
                         0 => {
                             sequence.start_idx = number;
                             // If this is the full selection, set this to the end index as well
-                            if selector.matches(":").count() == 0 {
+                            if components.len() == 1 {
                                 sequence.end_idx = number;
                             }
                         }
                         1 => sequence.end_idx = number,
-                        2 => sequence.step = number,
-                        _ => panic!("A selector cannot be more than three components long"),
+                        _ => return Err(invalid(idx, "too many components")),
                     }
                 }
                 Err(_e) => {
-                    let case_insensitive_regex = format!(r"(?i).*{}.*", &component);
+                    // `@` and a regex followed by +N or -N selects relative to the item it matches
+                    let (pattern, offset) = match split_anchor_offset(component) {
+                        Some((pattern, offset)) => (pattern, Some(offset)),
+                        None => (component.to_string(), None),
+                    };
+                    let component_regex =
+                        || selector_regex(selector, position, idx, &pattern, options);
                     match idx {
                         0 => {
-                            sequence.start_regex = Regex::new(&case_insensitive_regex).unwrap();
-                            // Set the start index to the usize max to ensure it doesn't interfere
-                            sequence.start_idx = usize::MAX;
+                            sequence.start_regex = Some(component_regex()?);
+                            sequence.start_offset = offset;
+                            // Set the start index to the i64 max to ensure it doesn't interfere
+                            sequence.start_idx = i64::MAX;
                             // If this is the full selection, set this to the end regex as well
-                            if selector.matches(":").count() == 0 {
-                                sequence.end_regex = Regex::new(&case_insensitive_regex).unwrap();
+                            if components.len() == 1 {
+                                sequence.end_regex = Some(component_regex()?);
+                                sequence.end_offset = offset;
                             }
                         }
-                        1 => sequence.end_regex = Regex::new(&case_insensitive_regex).unwrap(),
-                        2 => panic!("Step size must be an integer"),
-                        _ => panic!("A selector cannot be more than three components long"),
+                        1 => {
+                            sequence.end_regex = Some(component_regex()?);
+                            sequence.end_offset = offset;
+                        }
+                        2 => {
+                            // Likely a regex end written after the step, as in `error:2:warn`
+                            let reason = "step size must be an integer (ranges are start:end:step)";
+                            return Err(invalid(idx, reason));
+                        }
+                        _ => return Err(invalid(idx, "too many components")),
                     }
                 }
             }
@@ -120,5 +714,254 @@ pub fn parse_selectors(selectors: &String) -> Vec<Selector> {
         sequences.push(sequence);
     }
     // Return all selectors
-    sequences
+    Ok(sequences)
+}
+
+/// Parse selectors from a file, one or more comma-separated selectors per line
+/// Blank lines are skipped, so they don't select everything like an empty selector would
+pub fn parse_selectors_file(path: &str, options: &ParseOptions) -> Result<Vec<Selector>, OckError> {
+    let contents = fs::read_to_string(path)
+        .map_err(|source| OckError::Io { path: path.to_string(), source })?;
+    let mut sequences: Vec<Selector> = Vec::new();
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parsed = parse_selectors(line, options).map_err(|e| SelectorError::InFile {
+            path: path.to_string(),
+            line: line_idx + 1,
+            error: Box::new(e),
+        })?;
+        sequences.extend(parsed);
+    }
+    Ok(sequences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indices of the items any of the selectors match, resolved and matched the way main does
+    fn select(selectors: &str, items: &[&str], options: &ParseOptions) -> Vec<usize> {
+        let items: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+        let mut selectors = parse_selectors(selectors, options).unwrap();
+        for selector in selectors.iter_mut() {
+            selector.resolve_indices(items.len());
+            selector.resolve_anchors(&items);
+        }
+        (0..items.len())
+            .filter(|&idx| {
+                // Every selector sees every item, so their state stays right
+                let matched: Vec<bool> = selectors
+                    .iter_mut()
+                    .map(|selector| selector.matches(idx, &items[idx], items.len()))
+                    .collect();
+                matched.contains(&true)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn zero_based_index_selects_the_first_item() {
+        let options = ParseOptions { zero_based: true, ..ParseOptions::default() };
+        assert_eq!(select("0", &["a", "b", "c"], &options), vec![0]);
+        assert_eq!(select("1:2", &["a", "b", "c"], &options), vec![1, 2]);
+        assert_eq!(select("-1", &["a", "b", "c"], &options), vec![2]);
+    }
+
+    #[test]
+    fn zero_is_an_error_when_one_based() {
+        assert!(parse_selectors("0", &ParseOptions::default()).is_err());
+        assert_eq!(select("1", &["a", "b", "c"], &ParseOptions::default()), vec![0]);
+    }
+
+    /// Items named by their 1-based position, e.g. `row3`
+    fn rows(count: usize) -> Vec<String> {
+        (1..=count).map(|row| format!("row{}", row)).collect()
+    }
+
+    #[test]
+    fn step_without_an_end_runs_to_the_last_item() {
+        let rows = rows(12);
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        assert_eq!(select("2::3", &rows, &ParseOptions::default()), vec![1, 4, 7, 10]);
+        assert_eq!(select("5::2", &rows, &ParseOptions::default()), vec![4, 6, 8, 10]);
+    }
+
+    #[test]
+    fn escaped_colons_are_part_of_the_regex() {
+        let header = ["host", "http:status", "bytes"];
+        assert_eq!(select(r"http\:status", &header, &ParseOptions::default()), vec![1]);
+        assert_eq!(select(r"a\,b", &["x", "a,b"], &ParseOptions::default()), vec![1]);
+    }
+
+    const BLOCKS: [&str; 9] =
+        ["intro", "BEGIN", "one", "two", "END", "outside", "BEGIN", "three", "END"];
+
+    #[test]
+    fn end_regex_is_included_unless_the_end_is_exclusive() {
+        assert_eq!(select("BEGIN:END", &BLOCKS, &ParseOptions::default()), vec![1, 2, 3, 4]);
+        let options = ParseOptions { exclusive_end: true, ..ParseOptions::default() };
+        assert_eq!(select("BEGIN:END", &BLOCKS, &options), vec![1, 2, 3]);
+        // Only an end regex is left out, so numeric ends stay inclusive
+        assert_eq!(select("2:4", &BLOCKS, &options), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn repeat_ranges_captures_every_block() {
+        assert_eq!(select("BEGIN:END", &BLOCKS, &ParseOptions::default()), vec![1, 2, 3, 4]);
+        let options = ParseOptions { repeat_ranges: true, ..ParseOptions::default() };
+        assert_eq!(select("BEGIN:END", &BLOCKS, &options), vec![1, 2, 3, 4, 6, 7, 8]);
+        let options = ParseOptions { exclusive_end: true, ..options };
+        assert_eq!(select("BEGIN:END", &BLOCKS, &options), vec![1, 2, 3, 6, 7]);
+    }
+
+    #[test]
+    fn anchors_select_relative_to_the_item_they_match() {
+        let header = ["user", "command", "cpu", "mem", "x-1"];
+        let options = ParseOptions::default();
+        assert_eq!(select("@command+1:@command+2", &header, &options), vec![2, 3]);
+        assert_eq!(select("@cpu-1", &header, &options), vec![1]);
+        assert_eq!(select("@nothere+1", &header, &options), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn regexes_ending_in_a_number_are_not_anchors() {
+        let options = ParseOptions::default();
+        assert_eq!(select("x-1", &["user", "x-1", "x"], &options), vec![1]);
+        assert_eq!(select("utf-8", &["ascii", "utf-8"], &options), vec![1]);
+        assert_eq!(select("@c-1", &["a", "b", "c"], &options), vec![1]);
+        assert_eq!(select(r"@c\-1", &["a", "@c-1", "c"], &options), vec![1]);
+    }
+
+    #[test]
+    fn regex_starts_with_numeric_ends() {
+        let rows = ["intro", "foo", "a", "b", "foo", "c"];
+        let options = ParseOptions::default();
+        assert_eq!(select("foo:4", &rows, &options), vec![1, 2, 3]);
+        // An end before the start regex first matches leaves nothing to select
+        assert_eq!(select("foo:1", &rows, &options), Vec::<usize>::new());
+        assert_eq!(select("foo:2", &rows, &options), vec![1]);
+    }
+
+    #[test]
+    fn numeric_starts_with_regex_ends() {
+        let rows = ["intro", "a", "end", "b", "end"];
+        let options = ParseOptions::default();
+        assert_eq!(select("2:end", &rows, &options), vec![1, 2]);
+        assert_eq!(select("4:end", &rows, &options), vec![3, 4]);
+        assert_eq!(select("2:nothere", &rows, &options), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn steps_count_from_where_a_start_regex_matched() {
+        let block = ["intro", "BEGIN", "a", "b", "c", "d", "e", "END", "outro"];
+        let options = ParseOptions::default();
+        assert_eq!(select("BEGIN:END:2", &block, &options), vec![1, 3, 5, 7]);
+        assert_eq!(select("BEGIN:END:3", &block, &options), vec![1, 4, 7]);
+        // The end is only included when it falls on the step
+        assert_eq!(select("BEGIN:END:4", &block, &options), vec![1, 5]);
+    }
+
+    #[test]
+    fn dash_ranges_work_like_colon_ranges() {
+        let header = ["a", "b", "c", "d", "e", "f-2"];
+        let options = ParseOptions::default();
+        assert_eq!(select("1-3,5", &header, &options), vec![0, 1, 2, 4]);
+        assert_eq!(select("1-3,5", &header, &options), select("1:3,5", &header, &options));
+        assert_eq!(select("2-3,-1", &header, &options), vec![1, 2, 5]);
+        assert_eq!(select("-2", &header, &options), vec![4]);
+        assert_eq!(select("-3:-2", &header, &options), vec![3, 4]);
+        // Only two plain integers make a dash range, so regexes with dashes are left alone
+        assert_eq!(select("f-2", &header, &options), vec![5]);
+    }
+
+    #[test]
+    fn trailing_negative_ranges_include_the_last_item() {
+        let header = ["a", "b", "c", "d", "e"];
+        let options = ParseOptions::default();
+        assert_eq!(select("-3:", &header, &options), vec![2, 3, 4]);
+        assert_eq!(select("-3:-1", &header, &options), vec![2, 3, 4]);
+        assert_eq!(select("-2:", &header, &options), vec![3, 4]);
+        assert_eq!(select("-9:-4", &header, &options), vec![0, 1]);
+        assert_eq!(select("-5:-4", &["a", "b", "c"], &options), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn regexes_are_compiled_once_until_the_cache_is_cleared() {
+        // Tests each run on their own thread, so this cache is only this test's
+        clear_regex_cache();
+        get_or_compile_regex("cache[d]").unwrap();
+        get_or_compile_regex("cache[d]").unwrap();
+        assert_eq!(regex_cache_len(), 1);
+        assert!(get_or_compile_regex("(").is_err());
+        assert_eq!(regex_cache_len(), 1);
+        clear_regex_cache();
+        assert_eq!(regex_cache_len(), 0);
+    }
+
+    #[test]
+    fn globs_match_the_whole_item_rather_than_a_substring() {
+        let header = ["cpu", "cpux", "pct_cpu", "mem"];
+        let glob = ParseOptions { glob: true, ..ParseOptions::default() };
+        assert_eq!(select("cpu*", &header, &glob), vec![0, 1]);
+        assert_eq!(select("cpu", &header, &ParseOptions::default()), vec![0, 1, 2]);
+        assert_eq!(select("cpu", &header, &glob), vec![0]);
+        assert_eq!(select("*cpu", &header, &glob), vec![0, 2]);
+        assert_eq!(select("cpu?", &header, &glob), vec![1]);
+    }
+
+    #[test]
+    fn glob_classes_and_escapes() {
+        let header = ["a1", "b1", "c1", "*1", "a^"];
+        let glob = ParseOptions { glob: true, ..ParseOptions::default() };
+        assert_eq!(select("[ab]1", &header, &glob), vec![0, 1]);
+        assert_eq!(select("[!ab]1", &header, &glob), vec![2, 3]);
+        assert_eq!(select("[a-b]?", &header, &glob), vec![0, 1, 4]);
+        assert_eq!(select(r"\*1", &header, &glob), vec![3]);
+        assert_eq!(select("a[^]", &header, &glob), vec![4]);
+        assert_eq!(glob_to_regex("a.b*"), r"a\.b.*");
+    }
+
+    #[test]
+    fn modulo_selects_every_nth_item_by_remainder() {
+        let rows = rows(7);
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let options = ParseOptions::default();
+        assert_eq!(select("%2==0", &rows, &options), vec![1, 3, 5]);
+        assert_eq!(select("%3==2", &rows, &options), vec![1, 4]);
+        assert_eq!(select("%3==0", &rows, &options), vec![2, 5]);
+        // Zero-based indices leave the remainder unshifted
+        let zero_based = ParseOptions { zero_based: true, ..ParseOptions::default() };
+        assert_eq!(select("%2==0", &rows, &zero_based), vec![0, 2, 4, 6]);
+        assert_eq!(select("%3==2", &rows, &zero_based), vec![2, 5]);
+    }
+
+    #[test]
+    fn invalid_modulo_selectors_are_an_error() {
+        let options = ParseOptions::default();
+        assert!(parse_selectors("%0==0", &options).is_err());
+        assert!(parse_selectors("%3==3", &options).is_err());
+        assert!(parse_selectors("%3==4", &options).is_err());
+        assert!(parse_selectors("1:%2==0", &options).is_err());
+        assert!(parse_selectors("%2==0:5", &options).is_err());
+    }
+
+    #[test]
+    fn and_filters_narrow_a_range_to_matching_items() {
+        let header = ["host", "cpu_user", "mem", "cpu_sys", "cpu_idle", "disk"];
+        let options = ParseOptions::default();
+        assert_eq!(select("2:5&cpu", &header, &options), vec![1, 3, 4]);
+        assert_eq!(select("3:4&cpu", &header, &options), vec![3]);
+        assert_eq!(select(r"a\&b", &["x", "a&b"], &options), vec![1]);
+    }
+
+    #[test]
+    fn invalid_and_filters_are_an_error() {
+        let options = ParseOptions::default();
+        assert!(parse_selectors("a&b&c", &options).is_err());
+        assert!(parse_selectors("2:5&", &options).is_err());
+        assert!(parse_selectors("cpu&", &options).is_err());
+    }
 }
@@ -0,0 +1,170 @@
+use crate::utils;
+use regex::Regex;
+
+/// Where a fixed-width column starts and, unless it runs to the end of the row, ends, as 0-based
+/// inclusive character offsets
+pub type ColumnRange = (usize, Option<usize>);
+
+/// How rows are split into cells
+pub enum ColumnSplitter {
+    /// Cells are separated by matches of a delimiter regex, with empty cells left out
+    Delimiter(Regex),
+
+    /// Cells sit at fixed character offsets, with their padding trimmed off. Empty cells are kept,
+    /// so every row has a cell for every column.
+    FixedWidth(Vec<ColumnRange>),
+
+    /// Cells are CSV fields separated by a delimiter character, which quoted fields can contain.
+    /// Empty fields are kept, so cells line up with the fields.
+    Csv(char),
+}
+
+impl ColumnSplitter {
+    /// Split a row into its cells
+    pub fn split(&self, row: &str) -> Vec<String> {
+        match self {
+            ColumnSplitter::Delimiter(delimiter) => utils::split_by(row, delimiter),
+            ColumnSplitter::FixedWidth(_) => self
+                .offsets(row)
+                .iter()
+                .map(|&(start, end)| row[start..end].trim().to_string())
+                .collect(),
+            ColumnSplitter::Csv(_) => self
+                .offsets(row)
+                .iter()
+                .map(|&(start, end)| csv_unquote(&row[start..end]))
+                .collect(),
+        }
+    }
+
+    /// Split a row into its cells, keeping the delimiter after each cell on the end of it
+    pub fn split_preserving(&self, row: &str) -> Vec<String> {
+        match self {
+            ColumnSplitter::Delimiter(delimiter) => utils::split_by_preserving(row, delimiter),
+            ColumnSplitter::FixedWidth(_) | ColumnSplitter::Csv(_) => self.split(row),
+        }
+    }
+
+    /// Byte ranges (start, end) of the cells in a row. Slicing the row by these keeps whatever was
+    /// between cells, unlike rejoining split cells.
+    pub fn offsets(&self, row: &str) -> Vec<(usize, usize)> {
+        match self {
+            ColumnSplitter::Delimiter(delimiter) => utils::split_offsets(row, delimiter),
+            ColumnSplitter::FixedWidth(ranges) => ranges
+                .iter()
+                .map(|&(start, end)| {
+                    let start = byte_offset(row, start);
+                    let end = end.map_or(row.len(), |end| byte_offset(row, end + 1));
+                    (start, end.max(start))
+                })
+                .collect(),
+            ColumnSplitter::Csv(delimiter) => csv_field_offsets(row, *delimiter),
+        }
+    }
+}
+
+/// Byte offset of a character in text, or the end of the text if it's too short to have it
+fn byte_offset(text: &str, char_idx: usize) -> usize {
+    text.char_indices().nth(char_idx).map_or(text.len(), |(byte_idx, _)| byte_idx)
+}
+
+/// Parse fixed-width columns given as comma-separated character ranges, e.g. `0-7,8-15,16-`
+/// Offsets are 0-based and inclusive, and a range without an end runs to the end of the row.
+pub fn parse_column_ranges(spec: &str) -> Result<Vec<ColumnRange>, String> {
+    spec.split(',')
+        .map(|range| {
+            let invalid = |reason: &str| format!("column range '{}' {}", range, reason);
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let start = start.trim().parse::<usize>().map_err(|_| invalid("needs a start"))?;
+            let end = match end.trim() {
+                "" => None,
+                end => Some(end.parse::<usize>().map_err(|_| invalid("has an invalid end"))?),
+            };
+            if end.is_some_and(|end| end < start) {
+                return Err(invalid("ends before it starts"));
+            }
+            Ok((start, end))
+        })
+        .collect()
+}
+
+/// Split CSV text into records on newlines, except those inside quoted fields. Empty records are
+/// left out, as empty rows are when splitting on a row delimiter.
+pub fn split_csv_records(text: &str) -> Vec<String> {
+    let mut records: Vec<String> = Vec::new();
+    let mut record_start = 0;
+    let mut in_quotes = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            // A doubled quote inside a quoted field flips this twice, so it's left as it was
+            '"' => in_quotes = !in_quotes,
+            '\n' if !in_quotes => {
+                records.push(text[record_start..idx].to_string());
+                record_start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+    records.push(text[record_start..].to_string());
+    records.retain(|record| !record.is_empty());
+    records
+}
+
+/// Byte ranges (start, end) of the fields in a CSV record, including any quotes around them
+fn csv_field_offsets(record: &str, delimiter: char) -> Vec<(usize, usize)> {
+    let mut offsets: Vec<(usize, usize)> = Vec::new();
+    let mut field_start = 0;
+    let mut in_quotes = false;
+    for (idx, c) in record.char_indices() {
+        if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == delimiter && !in_quotes {
+            offsets.push((field_start, idx));
+            field_start = idx + c.len_utf8();
+        }
+    }
+    offsets.push((field_start, record.len()));
+    offsets
+}
+
+/// Get a CSV field's value, taking off the quotes around it and unescaping doubled quotes in it
+fn csv_unquote(field: &str) -> String {
+    match field.strip_prefix('"').and_then(|field| field.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_width_columns_are_trimmed_and_kept_when_empty() {
+        let splitter = ColumnSplitter::FixedWidth(parse_column_ranges("0-4,5-9,10-").unwrap());
+        assert_eq!(splitter.split("name  size  owner"), vec!["name", "size", "owner"]);
+        assert_eq!(splitter.split("a     "), vec!["a", "", ""]);
+        assert_eq!(splitter.split("日本語 12    x"), vec!["日本語 1", "2", "x"]);
+    }
+
+    #[test]
+    fn invalid_fixed_width_ranges_are_an_error() {
+        assert!(parse_column_ranges("5-2").is_err());
+        assert!(parse_column_ranges("a-2").is_err());
+        assert_eq!(parse_column_ranges("3,4-").unwrap(), vec![(3, Some(3)), (4, None)]);
+    }
+
+    #[test]
+    fn csv_records_only_end_on_newlines_outside_quotes() {
+        let text = "a,b\n\"multi\nline\",2\n\n3,4";
+        assert_eq!(split_csv_records(text), vec!["a,b", "\"multi\nline\",2", "3,4"]);
+    }
+
+    #[test]
+    fn csv_fields_are_unquoted_and_keep_their_delimiters() {
+        let splitter = ColumnSplitter::Csv(',');
+        let row = r#"a,"b,c","say ""hi""",,"#;
+        assert_eq!(splitter.split(row), vec!["a", "b,c", r#"say "hi""#, "", ""]);
+        assert_eq!(ColumnSplitter::Csv(';').split("a;b,c"), vec!["a", "b,c"]);
+    }
+}
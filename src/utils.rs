@@ -1,37 +1,323 @@
 mod utils {
     use regex::Regex;
 
-    /// Test is two regex expressions are equal
+    /// Test if two optional regex expressions are equal, with no regex only equal to no regex
     /// This needs to be done as there's no PartialEq provided by regex::Regex
     #[allow(dead_code)]
-    pub fn regex_eq(re1: &Regex, re2: &Regex) -> bool {
+    pub fn regex_eq(re1: &Option<Regex>, re2: &Option<Regex>) -> bool {
         // Convert both regexes to strings and check their equality
-        re1.as_str() == re2.as_str()
+        re1.as_ref().map(Regex::as_str) == re2.as_ref().map(Regex::as_str)
     }
 
-    /// Regex is default, which is the impossible regex ".^"
+    /// Test if an optional regex is set and matches text, as no regex matches nothing
     #[allow(dead_code)]
-    pub fn regex_is_default(re: &Regex) -> bool {
-        re.as_str() == ".^"
+    pub fn regex_matches(re: &Option<Regex>, text: &str) -> bool {
+        re.as_ref().is_some_and(|re| re.is_match(text))
     }
 
-    /// Split given text by a delimiter, returning a vector of Strings
+    /// Number of terminal columns text takes up. East Asian wide characters and emoji take two,
+    /// combining marks and zero-width characters take none, and everything else takes one.
     #[allow(dead_code)]
-    pub fn split(text: &String, delimiter: &String) -> Vec<String> {
-        if delimiter.is_empty() {
+    pub fn display_width(text: &str) -> usize {
+        text.chars()
+            .map(|c| match c as u32 {
+                // Combining marks, zero-width spaces and joiners, and variation selectors
+                0x0300..=0x036F
+                | 0x1AB0..=0x1AFF
+                | 0x1DC0..=0x1DFF
+                | 0x200B..=0x200F
+                | 0x20D0..=0x20FF
+                | 0xFE00..=0xFE0F
+                | 0xFE20..=0xFE2F => 0,
+                // Hangul Jamo, CJK, Hangul syllables, fullwidth forms, and emoji
+                0x1100..=0x115F
+                | 0x2E80..=0x303E
+                | 0x3041..=0x33FF
+                | 0x3400..=0x4DBF
+                | 0x4E00..=0x9FFF
+                | 0xA000..=0xA4CF
+                | 0xAC00..=0xD7A3
+                | 0xF900..=0xFAFF
+                | 0xFE30..=0xFE4F
+                | 0xFF00..=0xFF60
+                | 0xFFE0..=0xFFE6
+                | 0x1F300..=0x1F64F
+                | 0x1F680..=0x1F6FF
+                | 0x1F900..=0x1F9FF
+                | 0x20000..=0x3FFFD => 2,
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Put commas between each group of three digits in an integer, e.g. `129876` as `129,876`.
+    /// Text that isn't an integer is left as it is.
+    #[allow(dead_code)]
+    pub fn group_digits(text: &str) -> String {
+        let (sign, digits) = match text.strip_prefix(['-', '+']) {
+            Some(digits) => (&text[..1], digits),
+            None => ("", text),
+        };
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return text.to_string();
+        }
+        let mut grouped = String::from(sign);
+        for (idx, digit) in digits.chars().enumerate() {
+            if idx > 0 && (digits.len() - idx) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+
+    /// Squeeze each run of whitespace between words down to a single space, leaving any whitespace
+    /// at the start and end of the text as it was
+    #[allow(dead_code)]
+    pub fn collapse_whitespace(text: &str) -> String {
+        let body = text.trim();
+        if body.is_empty() {
+            return text.to_string();
+        }
+        let leading = &text[..text.len() - text.trim_start().len()];
+        let trailing = &text[text.trim_end().len()..];
+        let words: Vec<&str> = body.split_whitespace().collect();
+        format!("{}{}{}", leading, words.join(" "), trailing)
+    }
+
+    /// Replace tabs with spaces up to the next tab stop, every `tab_width` characters, like
+    /// `expand -t N`
+    #[allow(dead_code)]
+    pub fn expand_tabs(text: &str, tab_width: usize) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut column = 0;
+        for c in text.chars() {
+            match c {
+                '\t' => {
+                    let spaces = tab_width - column % tab_width;
+                    expanded.extend(std::iter::repeat_n(' ', spaces));
+                    column += spaces;
+                }
+                '\n' => {
+                    expanded.push(c);
+                    column = 0;
+                }
+                c => {
+                    expanded.push(c);
+                    column += 1;
+                }
+            }
+        }
+        expanded
+    }
+
+    /// Decode backslash escapes (e.g. `\n`, `\t`, `\0`) into the characters they represent
+    /// Used for output delimiters, which are printed as-is rather than treated as a regex
+    #[allow(dead_code)]
+    pub fn unescape(text: &str) -> String {
+        let mut output = String::new();
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                output.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => output.push('\n'),
+                Some('t') => output.push('\t'),
+                Some('r') => output.push('\r'),
+                Some('0') => output.push('\0'),
+                Some('s') => output.push(' '),
+                Some(other) => output.push(other),
+                // Keep a trailing backslash as-is
+                None => output.push('\\'),
+            }
+        }
+        output
+    }
+
+    /// Turn a delimiter into a regex pattern, swapping the `\0` escape (which regex doesn't
+    /// support) for `\x00`, so NUL can separate fields and records
+    #[allow(dead_code)]
+    pub fn delimiter_pattern(delimiter: &str) -> String {
+        let mut pattern = String::new();
+        let mut chars = delimiter.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                pattern.push(c);
+                continue;
+            }
+            // Keep other escapes (including an escaped backslash) for regex to interpret
+            match chars.next() {
+                Some('0') => pattern.push_str(r"\x00"),
+                Some(other) => {
+                    pattern.push(c);
+                    pattern.push(other);
+                }
+                None => pattern.push(c),
+            }
+        }
+        pattern
+    }
+
+    /// Turn a row or column delimiter into a regex pattern, so both interpret it the same way.
+    /// Escapes like `\t` and `\0` are decoded either way, and a `literal` delimiter is then
+    /// matched exactly rather than as a regex.
+    #[allow(dead_code)]
+    pub fn decode_delimiter(delimiter: &str, literal: bool) -> String {
+        if literal {
+            regex::escape(&unescape(delimiter))
+        } else {
+            delimiter_pattern(delimiter)
+        }
+    }
+
+    /// Split given text by a delimiter, returning a vector of Strings, or an error if the
+    /// delimiter isn't a valid regex
+    #[allow(dead_code)]
+    pub fn split(text: &str, delimiter: &str) -> Result<Vec<String>, regex::Error> {
+        Ok(split_by(text, &crate::selector::get_or_compile_regex(delimiter)?))
+    }
+
+    /// Split given text by an already compiled delimiter regex, returning a vector of Strings
+    /// Use this when splitting many times by the same delimiter, so it's only compiled once
+    #[allow(dead_code)]
+    pub fn split_by(text: &str, delimiter: &Regex) -> Vec<String> {
+        if delimiter.as_str().is_empty() {
             // Split by lines if empty delmiter passed. This should be faster than regex split
             text.lines()
-                .filter(|&s| s.is_empty() == false)
+                .filter(|&s| !s.is_empty())
                 .map(String::from)
                 .collect()
         } else {
             // Split by regex
-            Regex::new(delimiter)
-                .unwrap()
+            delimiter
                 .split(text)
-                .filter(|&s| s.is_empty() == false)
+                .filter(|&s| !s.is_empty())
                 .map(String::from)
                 .collect()
         }
     }
+
+    /// Split given text by a compiled delimiter regex, keeping each delimiter match on the end of
+    /// the field before it. Delimiters with no field before them (e.g. leading or repeated ones)
+    /// are dropped, so fields line up with `split_by`.
+    #[allow(dead_code)]
+    pub fn split_by_preserving(text: &str, delimiter: &Regex) -> Vec<String> {
+        if delimiter.as_str().is_empty() {
+            return split_by(text, delimiter);
+        }
+        let mut fields: Vec<String> = Vec::new();
+        let mut field_start = 0;
+        for delimiter_match in delimiter.find_iter(text) {
+            if delimiter_match.start() > field_start {
+                fields.push(text[field_start..delimiter_match.end()].to_string());
+            }
+            field_start = delimiter_match.end();
+        }
+        if text.len() > field_start {
+            fields.push(text[field_start..].to_string());
+        }
+        fields
+    }
+
+    /// Byte ranges (start, end) of the non-empty fields in text split by a compiled delimiter regex
+    /// Slicing the text by these keeps whatever was between fields, unlike rejoining split fields
+    #[allow(dead_code)]
+    pub fn split_offsets(text: &str, delimiter: &Regex) -> Vec<(usize, usize)> {
+        if delimiter.as_str().is_empty() {
+            // Nothing to split by, so the whole text is one field
+            return if text.is_empty() { Vec::new() } else { vec![(0, text.len())] };
+        }
+        let mut offsets: Vec<(usize, usize)> = Vec::new();
+        let mut field_start = 0;
+        for delimiter_match in delimiter.find_iter(text) {
+            if delimiter_match.start() > field_start {
+                offsets.push((field_start, delimiter_match.start()));
+            }
+            field_start = delimiter_match.end();
+        }
+        if text.len() > field_start {
+            offsets.push((field_start, text.len()));
+        }
+        offsets
+    }
+
+    /// Split given text by a compiled delimiter regex, keeping empty fields between delimiters
+    #[allow(dead_code)]
+    pub fn split_by_keep_empty(text: &str, delimiter: &Regex) -> Vec<String> {
+        delimiter.split(text).map(String::from).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn fixed_strings_split_on_literal_periods() {
+            let delimiter = decode_delimiter(".", true);
+            assert_eq!(split("a.b.c", &delimiter).unwrap(), vec!["a", "b", "c"]);
+        }
+
+        #[test]
+        fn fixed_strings_split_on_literal_pipes() {
+            let delimiter = decode_delimiter("|", true);
+            assert_eq!(split("a|b||c", &delimiter).unwrap(), vec!["a", "b", "c"]);
+        }
+
+        #[test]
+        fn regex_delimiters_are_still_regexes() {
+            let delimiter = decode_delimiter(r"\d+", false);
+            assert_eq!(split("a1b22c", &delimiter).unwrap(), vec!["a", "b", "c"]);
+        }
+
+        #[test]
+        fn invalid_delimiters_are_an_error() {
+            assert!(split("a(b", &decode_delimiter("(", false)).is_err());
+            assert_eq!(split("a(b", &decode_delimiter("(", true)).unwrap(), vec!["a", "b"]);
+        }
+
+        #[test]
+        fn group_digits_puts_commas_between_thousands() {
+            let cases = [
+                ("0", "0"),
+                ("12", "12"),
+                ("123", "123"),
+                ("1234", "1,234"),
+                ("123456", "123,456"),
+                ("1234567", "1,234,567"),
+                ("-1234", "-1,234"),
+                ("+123456", "+123,456"),
+                ("-123", "-123"),
+            ];
+            for (text, grouped) in cases {
+                assert_eq!(group_digits(text), grouped, "{}", text);
+            }
+            // Text that isn't an integer is left alone
+            for text in ["1234.5", "1e6", "12ab34", "1,234", "", "-", "+", "--12", "abc"] {
+                assert_eq!(group_digits(text), text, "{:?}", text);
+            }
+        }
+
+        #[test]
+        fn display_width_counts_terminal_columns() {
+            // Text, its width on the terminal, and its width counting every character as one
+            let cases = [
+                ("abc", 3, 3),
+                ("日本語", 6, 3),
+                ("한글", 4, 2),
+                ("ｆｕｌｌ", 8, 4),
+                ("🎉", 2, 1),
+                ("a🚀b", 4, 3),
+                ("cafe\u{301}", 4, 5),
+                ("n\u{303}o\u{308}", 2, 4),
+                ("a\u{200b}b", 2, 3),
+                ("日\u{301}🎉e", 5, 4),
+            ];
+            for (text, width, ascii_width) in cases {
+                assert_eq!(display_width(text), width, "{:?}", text);
+                assert_eq!(text.chars().count(), ascii_width, "{:?}", text);
+            }
+        }
+    }
 }
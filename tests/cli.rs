@@ -0,0 +1,434 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Output and exit code of running ock with arguments and stdin, and any environment variables
+fn run_with_env(args: &[&str], stdin: &str, env: &[(&str, &str)]) -> (String, i32) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ock"));
+    command
+        .args(args)
+        .env_remove("OCK_COLUMN_DELIMITER")
+        .env_remove("OCK_ROW_DELIMITER")
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    let mut child = command.spawn().expect("ock should run");
+    // ock can exit on a bad option before reading stdin, which closes the pipe early
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (String::from_utf8(output.stdout).unwrap(), output.status.code().unwrap_or(-1))
+}
+
+/// Output and exit code of running ock with arguments and stdin
+fn run(args: &[&str], stdin: &str) -> (String, i32) {
+    run_with_env(args, stdin, &[])
+}
+
+#[test]
+fn mixed_line_endings_leave_no_carriage_returns() {
+    let input = "name size\r\nfoo 1\nbar 2\r\nbaz 3\n";
+    assert_eq!(run(&["-c", "size"], input), (String::from("size\n1\n2\n3\n"), 0));
+    assert_eq!(run(&["-r", "2$"], input), (String::from("bar  2\n"), 0));
+}
+
+#[test]
+fn keep_cr_leaves_carriage_returns() {
+    // Whitespace delimiters would split the "\r" off as a delimiter, so use commas
+    let input = "name,size\r\nfoo,1\nbar,2\r\n";
+    let (output, _) = run(&["--keep-cr", "-d", ",", "-c", "size"], input);
+    assert_eq!(output, "size\r\n1\n2\r\n");
+}
+
+#[test]
+fn quiet_exits_like_a_normal_run() {
+    let input = "user pid\nroot 1\nbob 2\n";
+    let cases = [
+        vec!["-r", "user|root", "--output-format", "json"],
+        vec!["-r", "bob", "-c", "pid"],
+    ];
+    for args in cases {
+        let (_, code) = run(&args, input);
+        let quiet_args: Vec<&str> = [&["-q"], args.as_slice()].concat();
+        assert_eq!(run(&quiet_args, input), (String::new(), code), "{:?}", args);
+    }
+    let input = "a b\nx\ny z\n";
+    let args = ["-r", "x|z", "-c", "2", "--squeeze-blank-rows"];
+    let (_, code) = run(&args, input);
+    assert_eq!(run(&[&["-q"][..], &args].concat(), input).1, code);
+}
+
+#[test]
+fn header_repeats_after_every_n_data_rows() {
+    let input = "n\n1\n2\n3\n4\n5\n";
+    let (output, _) = run(&["-c", "n", "--header-repeat", "2"], input);
+    assert_eq!(output, "n\n1\n2\nn\n3\n4\nn\n5\n");
+}
+
+#[test]
+fn skip_drops_leading_rows_before_selecting() {
+    let input = "banner\n---\nfirst\nsecond\nthird\n";
+    assert_eq!(run(&["--skip", "2", "-r", "1"], input).0, "first\n");
+    assert_eq!(run(&["--skip", "2", "-r", "-3"], input).0, "first\n");
+    assert_eq!(run(&["--skip", "2", "-r", "-4"], input).1, 1);
+}
+
+#[test]
+fn single_field_with_a_literal_delimiter_acts_like_cut() {
+    // Empty fields count towards the index, and rows without the delimiter are output whole
+    let input = "a,b,c\n1,,3\nno delimiter\n";
+    assert_eq!(run(&["-d", ",", "-c", "3"], input).0, "c\n3\nno delimiter\n");
+}
+
+#[test]
+fn regex_delimiters_are_split_as_regexes() {
+    let input = "h1  h2\na   b\n";
+    assert_eq!(run(&["--column-delimiter", " +", "-c", "2"], input).0, "h2\nb\n");
+}
+
+#[test]
+fn cell_options_apply_with_a_single_field() {
+    let input = "a, b\n1,  2\n";
+    assert_eq!(run(&["-d", ",", "-c", "2", "--trim"], input).0, "b\n2\n");
+    let input = "a,b\n1,x    y\n";
+    let (output, _) = run(&["-d", ",", "-c", "2", "--collapse-whitespace"], input);
+    assert_eq!(output, "b\nx y\n");
+}
+
+#[test]
+fn structured_output_splits_the_header_with_a_single_field() {
+    let input = "a,b\n1,2\n";
+    let (output, _) = run(&["-d", ",", "-c", "2", "--output-format", "json"], input);
+    assert_eq!(output, "[\n  {\"b\": \"2\"}\n]\n");
+}
+
+#[test]
+fn delimiter_from_the_environment_doesnt_act_like_cut() {
+    let input = "a,b\n1,,2\n";
+    let env = [("OCK_COLUMN_DELIMITER", ",")];
+    assert_eq!(run_with_env(&["-c", "2"], input, &env).0, "b\n2\n");
+    assert_eq!(run(&["-d", ",", "-c", "2"], input).0, "b\n\n");
+}
+
+/// Output of `cut` for the same input, or None if it isn't installed
+fn cut(args: &[&str], stdin: &str) -> Option<String> {
+    let mut child = Command::new("cut")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    // ock can exit on a bad option before reading stdin, which closes the pipe early
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    Some(String::from_utf8(child.wait_with_output().unwrap().stdout).unwrap())
+}
+
+#[test]
+fn cut_compat_selects_the_same_fields_as_cut() {
+    let input = "a,b,c,d,e\n1,,3\n\nno delimiter\n,,,,,,\nx,y\n";
+    let field_lists = [("2-4", "2:4"), ("1,3", "1,3"), ("3-", "3:"), ("4-9", "4:9"), ("7", "7")];
+    for (fields, columns) in field_lists {
+        let Some(expected) = cut(&["-d", ",", "-f", fields], input) else { return };
+        let (output, _) = run(&["--cut-compat", "-d", ",", "-c", columns], input);
+        assert_eq!(output, expected, "-f {}", fields);
+    }
+}
+
+#[test]
+fn a_single_field_selects_the_same_field_as_cut() {
+    let input = "a,b,c\n1,,3\n\nno delimiter\n,,\n";
+    for field in ["1", "2", "3"] {
+        let Some(expected) = cut(&["-d", ",", "-f", field], input) else { return };
+        assert_eq!(run(&["-d", ",", "-c", field], input).0, expected, "-f {}", field);
+    }
+}
+
+#[test]
+fn html_with_no_columns_matched_is_an_empty_table() {
+    let input = "a b\n1 2\n";
+    let expected = (String::from("<table></table>\n"), 1);
+    assert_eq!(run(&["-c", "nothere", "--output-format", "html"], input), expected);
+}
+
+#[test]
+fn json_with_nothing_selected_is_still_json() {
+    let input = "pid cmd\n1 init\n";
+    let args = ["-r", "nothere", "-c", "pid,cmd", "--output-format", "json"];
+    assert_eq!(run(&args, input), (String::from("[]\n"), 1));
+    let args = [&args[..], &["--with-header-meta"]].concat();
+    let expected = "{\n  \"columns\": [\"pid\", \"cmd\"],\n  \"rows\": []\n}\n";
+    assert_eq!(run(&args, input), (String::from(expected), 1));
+}
+
+#[test]
+fn wide_characters_line_up_unless_every_character_is_one_column() {
+    let input = "name n\n日本 1\n🎉 2\ncafe\u{301} 3\n";
+    let expected = "name  n\n日本  1\n🎉    2\ncafe\u{301}  3\n";
+    assert_eq!(run(&["-c", "1,2"], input).0, expected);
+    let expected = "name   n\n日本     1\n🎉      2\ncafe\u{301}  3\n";
+    assert_eq!(run(&["-c", "1,2", "--ascii-width"], input).0, expected);
+}
+
+#[test]
+fn rows_matched_by_overlapping_steps_are_output_once() {
+    let input: String = (1..=12).map(|row| format!("{}\n", row)).collect();
+    assert_eq!(run(&["-r", "1:10:2,3:10:3"], &input).0, "1\n3\n5\n6\n7\n9\n");
+    // Each row is still output once when following the order of the selectors
+    let (output, _) = run(&["-r", "1:10:2,3:10:3", "--preserve-order"], &input);
+    assert_eq!(output, "1\n3\n5\n7\n9\n6\n");
+}
+
+#[test]
+fn flags_override_the_environment_which_overrides_the_defaults() {
+    let input = "a,b c\n1,2 3\n";
+    let env = [("OCK_COLUMN_DELIMITER", ",")];
+    assert_eq!(run(&["-c", "2"], input).0, "c\n3\n");
+    assert_eq!(run_with_env(&["-c", "2"], input, &env).0, "b c\n2 3\n");
+    assert_eq!(run_with_env(&["-d", " ", "-c", "2"], input, &env).0, "c\n3\n");
+    // An empty variable is the same as an unset one
+    assert_eq!(run_with_env(&["-c", "2"], input, &[("OCK_COLUMN_DELIMITER", "")]).0, "c\n3\n");
+
+    let input = "a b;c d;";
+    let env = [("OCK_ROW_DELIMITER", ";")];
+    assert_eq!(run_with_env(&["-c", "2"], input, &env).0, "b\nd\n");
+    let (output, _) = run_with_env(&["--row-delimiter", "\\n", "-c", "2"], input, &env);
+    assert_eq!(output, "b;c\n");
+}
+
+#[test]
+fn errors_exit_with_a_code_for_their_kind() {
+    let input = "a b\n1 2\n";
+    assert_eq!(run(&["-c", "1:2:x"], input), (String::new(), 2));
+    assert_eq!(run(&["--row-delimiter", "("], input), (String::new(), 3));
+    assert_eq!(run(&["--column-delimiter", "(", "-c", "1"], input), (String::new(), 3));
+    assert_eq!(run(&["--columns-file", "/nonexistent/columns"], input), (String::new(), 4));
+    assert_eq!(run(&["-c", "3", "--strict"], input), (String::new(), 5));
+}
+
+#[test]
+fn rows_ending_in_empty_cells_have_no_trailing_spaces() {
+    let input = "a,,c\n1,,\n";
+    assert_eq!(run(&["--csv-input"], input).0, "a  c\n1\n");
+}
+
+#[test]
+fn case_sensitive_and_ignore_case_override_each_other() {
+    let input = "Name size\nfoo 1\nFOO 2\n";
+    assert_eq!(run(&["-r", "foo"], input).0, "foo  1\nFOO  2\n");
+    assert_eq!(run(&["-r", "foo", "--case-sensitive"], input).0, "foo  1\n");
+    assert_eq!(run(&["-c", "name", "--case-sensitive"], input).1, 1);
+    // Whichever comes last wins
+    assert_eq!(run(&["-r", "FOO", "--case-sensitive", "-i"], input).0, "foo  1\nFOO  2\n");
+    assert_eq!(run(&["-r", "FOO", "-i", "--case-sensitive"], input).0, "FOO  2\n");
+}
+
+#[test]
+fn literal_row_delimiters_split_a_single_line_record() {
+    let input = "a b.c d.e f";
+    let args = ["--row-delimiter", ".", "--row-delimiter-literal", "-c", "2"];
+    assert_eq!(run(&args, input).0, "b\nd\nf\n");
+    // As a regex, a period matches every character, leaving nothing
+    assert_eq!(run(&["--row-delimiter", ".", "-c", "2"], input).1, 1);
+}
+
+#[test]
+fn nul_delimited_rows_round_trip() {
+    let input = "name size\0a b\0x y\0";
+    let args = ["--row-delimiter", "\\0", "--output-row-delimiter", "\\0", "-c", "2"];
+    assert_eq!(run(&args, input).0, "size\0b\0y\0");
+}
+
+#[test]
+fn unique_keeps_rows_in_the_order_they_were_first_seen() {
+    let input = "k v\nb 1\na 2\nb 1\nc 3\na 2\n";
+    assert_eq!(run(&["-c", "k", "--unique"], input).0, "k\nb\na\nc\n");
+    assert_eq!(run(&["-c", "k,v", "--unique"], input).0, "k  v\nb  1\na  2\nc  3\n");
+}
+
+#[test]
+fn input_without_a_trailing_newline_keeps_its_last_row() {
+    let input = "a b\n1 2";
+    assert_eq!(run(&["-c", "b"], input).0, "b\n2\n");
+    assert_eq!(run(&["-r", "2", "--no-trailing-newline"], input).0, "1  2");
+    assert_eq!(run(&["-r", "2", "--no-trailing-newline"], "a b\n1 2\n").0, "1  2");
+}
+
+#[test]
+fn columns_empty_in_every_row_take_no_space() {
+    let input = "a,,c\n1,,3\n4,,6\n";
+    assert_eq!(run(&["--csv-input"], input).0, "a  c\n1  3\n4  6\n");
+    // A column with anything in it keeps its place, even in rows where it's empty
+    assert_eq!(run(&["--csv-input"], "a,b,c\n1,,3\n").0, "a  b  c\n1     3\n");
+}
+
+#[test]
+fn cell_regex_selects_columns_with_any_matching_cell() {
+    let input = "12 345 6\n7 89 1000\n3 100 4\n";
+    let (output, _) = run(&["--no-header", "--cell-regex", r"\d\d\d"], input);
+    assert_eq!(output, "345  6\n89   1000\n100  4\n");
+    assert_eq!(run(&["--no-header", "--cell-regex", r"\d{5}"], input).1, 1);
+}
+
+#[test]
+fn sum_and_mean_only_leave_out_a_header_used_to_pick_columns() {
+    let input = "1\n2\n3\n4\n5\n";
+    assert_eq!(run(&["--sum", "1"], input).0, "15\n");
+    assert_eq!(run(&["--mean", "1"], input).0, "3\n");
+    let input = "name size\na 1\nb 2\n";
+    assert_eq!(run(&["-c", "size", "--sum", "1"], input).0, "3\n");
+    // Without column selectors the header is a row, so its name is skipped as a non-number
+    assert_eq!(run(&["--sum", "2"], input).0, "3\n");
+}
+
+#[test]
+fn join_merges_the_selected_cells_into_one_column() {
+    let input = "user host\nroot web1\nbob db2\n";
+    let (output, _) = run(&["-c", "user,host", "--join", "@"], input);
+    assert_eq!(output, "user@host\nroot@web1\nbob@db2\n");
+    // One column, so the JSON objects have one key
+    let (output, _) = run(&["-c", "user,host", "--join", "@", "--output-format", "json"], input);
+    assert_eq!(output, "[\n  {\"user@host\": \"root@web1\"},\n  {\"user@host\": \"bob@db2\"}\n]\n");
+}
+
+#[test]
+fn min_col_width_and_pad_char_change_the_padding() {
+    let input = "a bb\n111 2\n";
+    assert_eq!(run(&[], input).0, "a    bb\n111  2\n");
+    assert_eq!(run(&["--min-col-width", "5"], input).0, "a      bb\n111    2\n");
+    assert_eq!(run(&["--pad-char", "."], input).0, "a....bb\n111..2\n");
+    // Wider cells aren't cut down to the minimum
+    assert_eq!(run(&["--min-col-width", "2", "--pad-char", "-"], input).0, "a----bb\n111--2\n");
+}
+
+#[test]
+fn strict_checks_columns_without_matching_the_header() {
+    let input = "a,b\n1,2\n";
+    assert_eq!(run(&["-d", ",", "-c", "5", "--strict"], input), (String::new(), 5));
+    assert_eq!(run(&["-c", "5", "--strict", "--per-row-headers"], "a b\n1 2\n").1, 5);
+    assert_eq!(run(&["-d", ",", "-c", "2", "--strict"], input), (String::from("b\n2\n"), 0));
+}
+
+#[test]
+fn list_columns_reads_whole_csv_records() {
+    let input = "\"a\nb\",c\n1,2\n";
+    assert_eq!(run(&["--csv-input", "--list-columns"], input).0, "1: a\nb  2: c\n");
+    let (output, _) = run(&["--row-delimiter", ";", "--list-columns"], "a b;1 2;");
+    assert_eq!(output, "1: a  2: b\n");
+}
+
+#[test]
+fn preserve_order_outputs_rows_in_selector_order() {
+    let input: String = (1..=6).map(|row| format!("{}\n", row)).collect();
+    assert_eq!(run(&["-r", "5,1,3"], &input).0, "1\n3\n5\n");
+    assert_eq!(run(&["-r", "5,1,3", "--preserve-order"], &input).0, "5\n1\n3\n");
+    // Ranges keep their own order
+    assert_eq!(run(&["-r", "5:6,1:2", "--preserve-order"], &input).0, "5\n6\n1\n2\n");
+}
+
+#[test]
+fn tail_only_keeps_the_header_when_it_picked_columns() {
+    let input: String = (1..=1000).map(|row| format!("{}\n", row)).collect();
+    assert_eq!(run(&["--tail", "3"], &input).0, "998\n999\n1000\n");
+    assert_eq!(run(&["-r", "1:1000", "--tail", "3"], &input).0, "998\n999\n1000\n");
+    assert_eq!(run(&["--tail", "3", "--unique"], &input).0, "998\n999\n1000\n");
+    let input = "n\n1\n2\n3\n";
+    assert_eq!(run(&["-c", "n", "--tail", "2"], input).0, "n\n2\n3\n");
+    assert_eq!(run(&["-c", "n", "--tail", "2", "--sort-numeric", "1"], input).0, "n\n2\n3\n");
+}
+
+#[test]
+fn a_detected_delimiter_doesnt_act_like_cut() {
+    let input = "a,b,c\n1,,3\n";
+    assert_eq!(run(&["--auto-delimiter", "-c", "2"], input).0, "b\n3\n");
+    assert_eq!(run(&["--auto-delimiter", "-c", "2:3"], input).0, "b  c\n3\n");
+}
+
+#[test]
+fn per_row_indices_count_back_from_the_end_of_each_row() {
+    let input = "a b c\n1 2\n3 4 5 6\n";
+    assert_eq!(run(&["-c", "-1", "--per-row-indices"], input).0, "c\n2\n6\n");
+    assert_eq!(run(&["-c", "-2:-1", "--per-row-indices"], input).0, "b  c\n1  2\n5  6\n");
+    // Otherwise the header decides, which misses the last cell of longer rows
+    assert_eq!(run(&["-c", "-1"], input).0, "c\n\n5\n");
+}
+
+#[test]
+fn group_by_blank_matches_each_stacked_table_on_its_own_header() {
+    let input = "a b\n1 2\n3 4\n\nb a\n5 6\n7 8\n";
+    assert_eq!(run(&["-c", "b", "--group-by-blank"], input).0, "b\n2\n4\n\nb\n5\n7\n");
+    let (output, _) = run(&["-c", "a,b", "--group-by-blank", "--output-format", "json"], input);
+    let expected = "[\n  {\"a\": \"1\", \"b\": \"2\"},\n  {\"a\": \"3\", \"b\": \"4\"},\n  \
+                    {\"a\": \"6\", \"b\": \"5\"},\n  {\"a\": \"8\", \"b\": \"7\"}\n]\n";
+    assert_eq!(output, expected);
+    // Table headers and separators aren't data, so options treating every row as data can't be used
+    for option in [&["--sort", "1"][..], &["--unique"], &["--tail", "1"]] {
+        let args = [&["-c", "b", "--group-by-blank"][..], option].concat();
+        assert_eq!(run(&args, input), (String::new(), 2), "{:?}", option);
+    }
+}
+
+#[test]
+fn paragraphs_are_split_on_runs_of_blank_lines() {
+    let input = "\n\na b\nc d\n\n\n \n\ne f\n\n";
+    assert_eq!(run(&["--paragraph"], input).0, "a b\nc d\n\ne f\n");
+    assert_eq!(run(&["--paragraph", "-r", "2"], input).0, "e f\n");
+    assert_eq!(run(&["--paragraph", "--output-row-delimiter", ";"], input).0, "a b\nc d;e f;");
+}
+
+#[test]
+fn crlf_paragraphs_leave_no_carriage_returns() {
+    let input = "a b\r\nc d\r\n\r\n\r\ne f\r\n";
+    assert_eq!(run(&["--paragraph"], input).0, "a b\nc d\n\ne f\n");
+    assert_eq!(run(&["--paragraph", "-r", "1"], input).0, "a b\nc d\n");
+}
+
+#[test]
+fn align_by_index_keeps_ragged_cells_under_their_own_column() {
+    let input = "a b c d\n1 2\n3 4 5 6\n";
+    let args = ["-c", "1,-1", "--per-row-indices"];
+    let aligned = run(&[&args[..], &["--align-by-index"]].concat(), input).0;
+    assert_eq!(aligned, "a     d\n1  2\n3     6\n");
+    // Otherwise the short row's last cell lines up with the other rows' last cells
+    assert_eq!(run(&args, input).0, "a  d\n1  2\n3  6\n");
+    // Cells missing from the end of short rows leave nothing behind
+    assert_eq!(run(&["-c", "2,4", "--align-by-index"], input).0, "b  d\n2\n4  6\n");
+}
+
+#[test]
+fn expand_tabs_counts_indentation_toward_tab_stops() {
+    let input = "name\tsize\tkind\n\tfoo\t12\tfile\n\tbarbaz\t3\tdir\n";
+    assert_eq!(run(&["--expand-tabs", "4", "-c", "2"], input).0, "size\n12\n3\n");
+    // The leading tab moves each row's later tab stops, so the spacing kept between cells varies
+    let args = ["--expand-tabs", "4", "-c", "1:2", "--preserve-spacing"];
+    assert_eq!(run(&args, input).0, "name    size\nfoo 12\nbarbaz  3\n");
+    let args = ["--expand-tabs", "8", "-c", "1:2", "--preserve-spacing"];
+    assert_eq!(run(&args, input).0, "name    size\nfoo     12\nbarbaz  3\n");
+}
+
+#[test]
+fn list_columns_reads_json_input_whole() {
+    let input = "[\n  {\"pid\": 1,\n   \"cmd\": \"init\"},\n  {\"tty\": \"pts/0\"}\n]\n";
+    let (output, _) = run(&["--input-format", "json", "--list-columns"], input);
+    assert_eq!(output, "1: pid  2: cmd  3: tty\n");
+}
+
+#[test]
+fn count_unique_only_leaves_out_a_header_column_selectors_used() {
+    assert_eq!(run(&["--count-unique", "1"], "b\na\nb\n").0, "2  b\n1  a\n");
+    let input = "user pid\nroot 1\nbob 7\nroot 2\nalice 9\n";
+    assert_eq!(run(&["-c", "user", "--count-unique", "1"], input).0, "2  root\n1  alice\n1  bob\n");
+    assert_eq!(run(&["-r", "2:", "--count-unique", "1"], input).0, "2  root\n1  alice\n1  bob\n");
+}
+
+#[test]
+fn header_only_prints_the_header_cells_selectors_pick() {
+    let input = "pid cpu_user mem cpu_sys\n1 2 3 4\n5 6 7 8\n";
+    assert_eq!(run(&["--header-only", "-c", "2,4"], input).0, "cpu_user  cpu_sys\n");
+    assert_eq!(run(&["--header-only", "-c", "-1"], input).0, "cpu_sys\n");
+    assert_eq!(run(&["--header-only", "-c", "cpu"], input).0, "cpu_user  cpu_sys\n");
+    assert_eq!(run(&["--header-only", "-c", "^cpu_s"], input).0, "cpu_sys\n");
+    assert_eq!(run(&["--header-only", "-c", "1,mem"], input).0, "pid  mem\n");
+    assert_eq!(run(&["--header-only", "-c", "2:mem"], input).0, "cpu_user  mem\n");
+    assert_eq!(run(&["--header-only"], input).0, "pid  cpu_user  mem  cpu_sys\n");
+    assert_eq!(run(&["--header-only", "-c", "nothere"], input), (String::new(), 1));
+}